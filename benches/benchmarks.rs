@@ -1,14 +1,126 @@
+use std::time::{Duration, Instant};
 use sandbox::engine::{Sandbox, UserEvent, Kind};
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sandbox::render::{FrameBuffer, Renderer};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+use criterion::measurement::WallTime;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+/// Scenes are restored after this many ticks, so they don't settle into an
+/// idle world halfway through a benchmark.
+const TICKS_PER_SCENE: u32 = 50;
+
+const SIZES: [i32; 2] = [400, 1000];
+
+fn scene(size: i32, kind_at: impl Fn(i32, i32, &mut StdRng) -> Kind) -> Sandbox {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ascii: Vec<String> = (0..size)
+        .map(|y| (0..size).map(|x| kind_at(x, y, &mut rng).to_char()).collect())
+        .collect();
+    let mut sandbox = Sandbox::from_ascii(&ascii.join("\n")).unwrap();
+    sandbox.seed(0);
+    sandbox
+}
+
+/// Scenes of the materials the app ships with: Sand runs as a compiled
+/// rule and Water natively, while Plant and Fire don't move. See
+/// `benches/wasm.js` for the same scenes in the wasm build.
+fn scenes(size: i32) -> Vec<(&'static str, Sandbox)> {
+    vec![
+        ("sand", scene(size, |_, y, rng| {
+            if y < size / 2 && rng.gen_bool(0.5) { Kind::Sand } else { Kind::Empty }
+        })),
+        ("water", scene(size, |_, y, _| if y < size / 2 { Kind::Water } else { Kind::Empty })),
+        ("sand on water", scene(size, |_, y, _| {
+            if y < size / 3 { Kind::Sand } else if y >= size / 2 { Kind::Water } else { Kind::Empty }
+        })),
+        ("mostly idle", scene(size, |x, y, _| {
+            if y == size - 1 || (y == 0 && x % 50 == 0) { Kind::Sand } else { Kind::Empty }
+        })),
+    ]
+}
+
+/// Times single ticks of `sandbox`, each with the event `event` gives for
+/// its tick number.
+fn bench_ticks(group: &mut BenchmarkGroup<WallTime>, name: &str, mut sandbox: Sandbox,
+               event: impl Fn(u32) -> Option<UserEvent>) {
+    let snapshot = sandbox.snapshot();
+    let mut ticks = 0;
+    group.bench_function(name, |b| b.iter_custom(|iterations| {
+        let mut total = Duration::default();
+        for _ in 0..iterations {
+            if ticks == TICKS_PER_SCENE {
+                sandbox.restore(&snapshot).unwrap();
+                ticks = 0;
+            }
+            let event = event(ticks);
+            let start = Instant::now();
+            sandbox.tick(black_box(event));
+            total += start.elapsed();
+            ticks += 1;
+        }
+        total
+    }));
+}
+
+fn ticks(c: &mut Criterion) {
+    for &size in &SIZES {
+        let mut group = c.benchmark_group(format!("tick {0}x{0}", size));
+        group.throughput(Throughput::Elements((size * size) as u64));
+        if size > 400 {
+            group.sample_size(10);
+        }
+
+        for (name, sandbox) in scenes(size) {
+            bench_ticks(&mut group, name, sandbox, |_| None);
+        }
+        bench_ticks(&mut group, "brush", scene(size, |_, _, _| Kind::Empty), |tick| {
+            let x = (tick as i32 * 7) % size;
+            Some(UserEvent::new(x, size / 4, Kind::Sand, 20))
+        });
+        bench_ticks(&mut group, "spray", scene(size, |_, _, _| Kind::Empty), |tick| {
+            let x = (tick as i32 * 7) % size;
+            Some(UserEvent { tool: Some("spray".to_string()), ..UserEvent::new(x, size / 4, Kind::Water, 20) })
+        });
+        group.finish();
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render rgba");
+    for &size in &SIZES {
+        let (_, sandbox) = scenes(size).into_iter().find(|(name, _)| *name == "sand on water").unwrap();
+        let mut frame = FrameBuffer::new(size, size);
+
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_function(format!("{0}x{0}", size), |b| b.iter(|| {
+            frame.render(&sandbox);
+            black_box(frame.data());
+        }));
+
+        let sandbox = scene(size, |x, y, rng| {
+            if y == size - 1 && x % 10 == 0 {
+                Kind::Fire
+            } else if y >= size / 2 && rng.gen_bool(0.7) {
+                Kind::Plant
+            } else {
+                Kind::Empty
+            }
+        });
+        frame.set_lighting(true);
+        group.bench_function(format!("{0}x{0} lit", size), |b| b.iter(|| {
+            frame.render(&sandbox);
+            black_box(frame.data());
+        }));
+        frame.set_lighting(false);
+    }
+    group.finish();
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("sand 20", |b| b.iter(|| {
         let mut sandbox = Sandbox::new(50, 50);
-        sandbox.tick(Some(UserEvent {
-            x: 0, y: 0,
-            kind: Kind::Sand,
-            size: 20
-        }));
+        sandbox.tick(Some(UserEvent::new(0, 0, Kind::Sand, 20)));
         for _ in 0..20 {
             sandbox.tick(None);
         }
@@ -16,16 +128,12 @@ fn criterion_benchmark(c: &mut Criterion) {
 
     c.bench_function("sand 50", |b| b.iter(|| {
         let mut sandbox = Sandbox::new(50, 50);
-        sandbox.tick(Some(UserEvent {
-            x: 0, y: 0,
-            kind: Kind::Sand,
-            size: 50
-        }));
+        sandbox.tick(Some(UserEvent::new(0, 0, Kind::Sand, 50)));
         for _ in 0..20 {
             sandbox.tick(None);
         }
     }));
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, ticks, render);
 criterion_main!(benches);
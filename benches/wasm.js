@@ -0,0 +1,51 @@
+// Times ticks of the materials the app ships in the wasm build, where Sand
+// runs as a compiled rule (see `compiler::CompiledRule`) and Water natively.
+// The Rust benchmarks only time a native build.
+//
+//     npm run bench:wasm
+const path = require("path");
+const { SandboxHandle } = require(path.resolve(__dirname, "../target/wasm-bench"));
+
+const SIZE = 400;
+// Scenes are restored after this many ticks, as in `benchmarks.rs`.
+const TICKS_PER_SCENE = 50;
+const ROUNDS = 20;
+
+const scenes = {
+  "sand": (x, y) => y < SIZE / 2 && (x + y) % 2 === 0 ? "sand" : null,
+  "water": (x, y) => y < SIZE / 2 ? "water" : null,
+  "sand on water": (x, y) => y < SIZE / 3 ? "sand" : y >= SIZE / 2 ? "water" : null,
+  "mostly idle": (x, y) => y === SIZE - 1 || (y === 0 && x % 50 === 0) ? "sand" : null,
+};
+
+function scene(materialAt) {
+  const sandbox = new SandboxHandle(SIZE, SIZE, 0);
+  for (let y = 0; y < SIZE; y++) {
+    for (let x = 0; x < SIZE; x++) {
+      const material = materialAt(x, y);
+      if (material !== null) {
+        sandbox.paint(x, y, material, 0);
+      }
+    }
+  }
+  sandbox.tick();
+  return sandbox;
+}
+
+for (const [name, materialAt] of Object.entries(scenes)) {
+  const sandbox = scene(materialAt);
+  const snapshot = sandbox.snapshot();
+  const rounds = [];
+  for (let round = 0; round < ROUNDS; round++) {
+    sandbox.restore(snapshot);
+    const start = process.hrtime.bigint();
+    for (let tick = 0; tick < TICKS_PER_SCENE; tick++) {
+      sandbox.tick();
+    }
+    rounds.push(Number(process.hrtime.bigint() - start) / TICKS_PER_SCENE);
+  }
+  rounds.sort((a, b) => a - b);
+  const median = rounds[ROUNDS >> 1];
+  console.log(`${name}: ${(median / 1e6).toFixed(2)} ms per tick, ${(median / (SIZE * SIZE)).toFixed(1)} ns per cell`);
+  sandbox.free();
+}
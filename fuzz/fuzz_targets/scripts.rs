@@ -0,0 +1,40 @@
+//! Runs arbitrary sources as a material script, a hook and a tool on a small
+//! world. Whatever the script does, ticks must finish without panicking and
+//! leave the world the size it was. Run with `cargo fuzz run scripts`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sandbox::engine::{Kind, Sandbox, UserEvent, MAX_ERRORS};
+use sandbox::events::Hook;
+
+const WORLD: &str = "
+    ..S..W..
+    .PPP....
+    ....F...
+    SSWW.PP.
+";
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let mut sandbox = Sandbox::from_ascii(WORLD).unwrap();
+    sandbox.seed(0);
+    let scripted = sandbox.register_script(Kind::Plant, source).is_ok();
+    let _ = sandbox.register_hook(Kind::Sand, Hook::Contact, source);
+    let tool = sandbox.register_tool("fuzz", source).is_ok();
+    if !scripted && !tool {
+        return;
+    }
+
+    sandbox.tick(Some(UserEvent::new(3, 1, Kind::Plant, 1)));
+    sandbox.tick(Some(UserEvent { tool: Some("fuzz".to_string()), ..UserEvent::new(4, 2, Kind::Water, 2) }));
+    sandbox.tick(None);
+    assert!(sandbox.errors().len() <= MAX_ERRORS);
+
+    let ascii = sandbox.to_ascii();
+    assert_eq!(ascii.lines().count(), 4);
+    assert!(ascii.lines().all(|row| row.len() == 8 && !row.contains('#')));
+});
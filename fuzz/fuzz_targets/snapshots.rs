@@ -0,0 +1,22 @@
+//! Loads arbitrary bytes as a snapshot. Invalid ones must be rejected without
+//! changing the sandbox, and valid ones must tick and save again. Run with
+//! `cargo fuzz run snapshots`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use sandbox::engine::Sandbox;
+
+fuzz_target!(|data: &[u8]| {
+    let mut sandbox = Sandbox::new(4, 4);
+    let before = sandbox.snapshot();
+    if sandbox.restore(data).is_err() {
+        assert_eq!(sandbox.snapshot(), before);
+        return;
+    }
+
+    sandbox.tick(None);
+    let saved = sandbox.snapshot();
+    let mut restored = Sandbox::new(1, 1);
+    restored.restore(&saved).unwrap();
+    assert_eq!(restored.snapshot(), saved);
+});
@@ -1,3 +1,67 @@
+import { setUpToolbar } from "./toolbar.js";
+
 import("../pkg/index.js").catch(console.error).then((app) => {
-    app.run();
+    // Kept on `window` so stats and other handle methods are reachable from the console.
+    window.sandbox = app.run();
+
+    setUpToolbar(window.sandbox, document.getElementById("toolbar"), document.getElementById("bindings"));
+
+    const generator = document.getElementById("generator");
+    for (const name of window.sandbox.generators()) {
+        generator.add(new Option(name, name));
+    }
+
+    const seed = document.getElementById("seed");
+    document.getElementById("generate").addEventListener("click", () => {
+        if (seed.value === "") {
+            seed.value = Math.floor(Math.random() * 0xffffffff);
+        }
+        window.sandbox.generate(generator.value, Number(seed.value) >>> 0);
+    });
+
+    const lighting = document.getElementById("lighting");
+    window.sandbox.set_lighting(lighting.checked);
+    lighting.addEventListener("change", () => window.sandbox.set_lighting(lighting.checked));
+
+    // Shows what the cell under the pointer holds. It keeps showing the last
+    // one while the pointer is away, so it can be read without hovering.
+    const inspector = document.getElementById("inspector");
+    const canvas = document.getElementById("canvas");
+    let hovering = false;
+    canvas.addEventListener("pointerenter", () => hovering = true);
+    canvas.addEventListener("pointerleave", () => hovering = false);
+    const inspect = () => {
+        if (hovering) {
+            const [x, y] = window.sandbox.hovered();
+            const cell = window.sandbox.inspect(x, y);
+            if (cell !== null) {
+                const fields = Object.entries(cell.fields).map(([name, value]) => `${name}: ${+value.toFixed(3)}`);
+                inspector.textContent = [
+                    `${cell.material} at (${cell.x}, ${cell.y})`,
+                    `color: ${cell.color}`,
+                    `clock: ${cell.clock}`,
+                    ...fields,
+                ].join("\n");
+            }
+        }
+        requestAnimationFrame(inspect);
+    };
+    requestAnimationFrame(inspect);
+
+    const overlay = document.getElementById("overlay");
+    const legend = document.getElementById("legend");
+    for (const name of window.sandbox.overlays()) {
+        overlay.add(new Option(name, name));
+    }
+    overlay.addEventListener("change", () => {
+        window.sandbox.set_overlay(overlay.value);
+        legend.replaceChildren(...window.sandbox.legend().map(({ label, color }) => {
+            const entry = document.createElement("span");
+            entry.textContent = label;
+            entry.style.borderLeft = `1em solid ${color}`;
+            entry.style.margin = "0 0.5em";
+            entry.style.paddingLeft = "0.25em";
+            return entry;
+        }));
+    });
 });
@@ -0,0 +1,164 @@
+// The palette toolbar and the keys that drive it. Bindings map keys to
+// actions and are kept in localStorage, so changes survive reloads.
+
+const STORAGE_KEY = "sandbox.bindings";
+
+// Actions are `material:<name>`, `tool:<name>`, with `tool:brush` for the
+// plain brush, and `size:<change>`.
+export const DEFAULT_BINDINGS = {
+    "1": "material:sand",
+    "2": "material:plant",
+    "3": "material:fire",
+    "4": "material:water",
+    "e": "material:empty",
+    "b": "tool:brush",
+    "s": "tool:spray",
+    "m": "tool:smudge",
+    "h": "tool:heat",
+    "v": "tool:vacuum",
+    "r": "tool:replace",
+    "+": "size:+1",
+    "-": "size:-1",
+};
+
+export function loadBindings() {
+    try {
+        const stored = JSON.parse(localStorage.getItem(STORAGE_KEY));
+        if (stored !== null && typeof stored === "object") {
+            return stored;
+        }
+    } catch (error) {
+        console.warn("ignoring stored key bindings", error);
+    }
+    return { ...DEFAULT_BINDINGS };
+}
+
+function saveBindings(bindings) {
+    localStorage.setItem(STORAGE_KEY, JSON.stringify(bindings));
+}
+
+function perform(sandbox, action) {
+    const [type, value] = action.split(":");
+    switch (type) {
+        case "material":
+            return sandbox.set_material(value);
+        case "tool":
+            return sandbox.set_tool(value === "brush" ? null : value);
+        case "size":
+            sandbox.set_brush_size(Math.max(1, sandbox.brush().size + Number(value)));
+            return true;
+        default:
+            return false;
+    }
+}
+
+// Fills `toolbar` with a button per material and tool, and `editor` with a
+// way to rebind every action.
+export function setUpToolbar(sandbox, toolbar, editor) {
+    let bindings = loadBindings();
+    const keyFor = (action) => Object.keys(bindings).find((key) => bindings[key] === action);
+    const buttons = [];
+
+    const button = (action, label, color) => {
+        const element = document.createElement("button");
+        if (color !== undefined) {
+            const swatch = document.createElement("span");
+            swatch.style.display = "inline-block";
+            swatch.style.width = swatch.style.height = "0.8em";
+            swatch.style.marginRight = "0.3em";
+            swatch.style.background = color;
+            swatch.style.border = "1px solid #888";
+            element.append(swatch);
+        }
+        element.append(label);
+        element.addEventListener("click", () => {
+            perform(sandbox, action);
+            update();
+        });
+        buttons.push({ action, element });
+        toolbar.append(element);
+    };
+
+    for (const { name, color } of sandbox.materials()) {
+        button(`material:${name}`, name, color);
+    }
+    toolbar.append(" ");
+    button("tool:brush", "brush");
+    for (const name of sandbox.tools()) {
+        button(`tool:${name}`, name);
+    }
+    toolbar.append(" size ");
+    button("size:-1", "-");
+    const size = document.createElement("span");
+    toolbar.append(size);
+    button("size:+1", "+");
+
+    const update = () => {
+        const brush = sandbox.brush();
+        const selected = [`material:${brush.material}`, `tool:${brush.tool === null ? "brush" : brush.tool}`];
+        for (const { action, element } of buttons) {
+            element.style.fontWeight = selected.includes(action) ? "bold" : "";
+            const key = keyFor(action);
+            element.title = key === undefined ? "" : `key: ${key}`;
+        }
+        size.textContent = ` ${brush.size} `;
+        renderEditor();
+    };
+
+    // The action waiting for a key to be pressed, while rebinding.
+    let rebinding = null;
+
+    const renderEditor = () => {
+        const rows = buttons.map(({ action }) => {
+            const row = document.createElement("div");
+            const key = document.createElement("button");
+            const current = keyFor(action);
+            key.textContent = rebinding === action ? "press a key…" : (current === undefined ? "unbound" : current);
+            key.addEventListener("click", () => {
+                rebinding = action;
+                renderEditor();
+            });
+            row.append(key, ` ${action}`);
+            return row;
+        });
+        const reset = document.createElement("button");
+        reset.textContent = "Reset to defaults";
+        reset.addEventListener("click", () => {
+            bindings = { ...DEFAULT_BINDINGS };
+            saveBindings(bindings);
+            update();
+        });
+        editor.replaceChildren(...rows, reset);
+    };
+
+    document.addEventListener("keydown", (event) => {
+        if (event.ctrlKey || event.metaKey || event.altKey) {
+            return;
+        }
+        if (rebinding !== null) {
+            event.preventDefault();
+            if (event.key !== "Escape") {
+                for (const key of Object.keys(bindings)) {
+                    if (bindings[key] === rebinding) {
+                        delete bindings[key];
+                    }
+                }
+                bindings[event.key] = rebinding;
+                saveBindings(bindings);
+            }
+            rebinding = null;
+            update();
+            return;
+        }
+        const tag = event.target.tagName;
+        if (tag === "INPUT" || tag === "SELECT") {
+            return;
+        }
+        const action = bindings[event.key];
+        if (action !== undefined && perform(sandbox, action)) {
+            update();
+        }
+    });
+
+    update();
+}
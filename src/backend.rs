@@ -0,0 +1,80 @@
+//! Interpreters material scripts can be written for. `RhaiBackend` is the
+//! default; others are registered with `Sandbox::register_script_with`.
+
+use std::cell::RefMut;
+use std::fmt;
+use rand::rngs::StdRng;
+use rhai::Dynamic;
+use crate::engine::{Particle, WorldView};
+use crate::scripting::{ScriptRng, ScriptState};
+
+/// Compiles scripts once and runs them for every cell of their material.
+pub trait ScriptBackend {
+    type Script;
+    type Error: fmt::Display;
+
+    fn compile(&mut self, source: &str) -> Result<Self::Script, Self::Error>;
+
+    /// Runs `script` for the cell `host` is centered on.
+    fn run(&mut self, script: &Self::Script, host: &mut Host) -> Result<(), Self::Error>;
+}
+
+/// What the sandbox exposes to a script run for one cell: the same `view`,
+/// `current`, `clock`, `rng`, `state` and `global` Rhai scripts see.
+pub struct Host {
+    pub(crate) current: Particle,
+    pub(crate) view: WorldView,
+    pub(crate) clock: u8,
+    pub(crate) rng: ScriptRng,
+    pub(crate) state: ScriptState,
+    pub(crate) global: ScriptState,
+}
+
+impl Host {
+    pub(crate) fn new(current: Particle, view: WorldView, clock: u8, rng: ScriptRng,
+                      state: ScriptState, global: ScriptState) -> Self {
+        Self {
+            current,
+            view,
+            clock,
+            rng,
+            state,
+            global,
+        }
+    }
+
+    pub fn current(&self) -> Particle {
+        self.current
+    }
+
+    /// The world, centered on the cell.
+    pub fn view(&mut self) -> &mut WorldView {
+        &mut self.view
+    }
+
+    pub fn clock(&self) -> u8 {
+        self.clock
+    }
+
+    pub fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
+    /// A value the material's scripts stored, which outlives the tick.
+    pub fn state(&self, key: &str) -> Option<Dynamic> {
+        self.state.value(key)
+    }
+
+    pub fn set_state(&mut self, key: &str, value: Dynamic) {
+        self.state.insert(key, value);
+    }
+
+    /// A value shared by the scripts of every material.
+    pub fn global(&self, key: &str) -> Option<Dynamic> {
+        self.global.value(key)
+    }
+
+    pub fn set_global(&mut self, key: &str, value: Dynamic) {
+        self.global.insert(key, value);
+    }
+}
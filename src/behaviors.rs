@@ -0,0 +1,36 @@
+use rand::Rng;
+use crate::engine::{Behavior, Kind, WorldView, EMPTY};
+
+/// Falls like sand and puts out fire, but also spreads sideways when it can't fall.
+pub struct Water;
+
+impl Water {
+    /// Moves into an empty cell, or onto fire, putting it out.
+    fn flow(view: &mut WorldView, dx: i32, dy: i32) {
+        if view.get(dx, dy).kind == Kind::Fire {
+            let current = view.get(0, 0);
+            view.set(dx, dy, current);
+            view.set(0, 0, EMPTY);
+        } else {
+            view.swap(dx, dy);
+        }
+    }
+}
+
+impl<R: Rng> Behavior<R> for Water {
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        let current = view.get(0, 0);
+        let dx = if rng.gen_bool(0.5) { -1 } else { 1 };
+        let below = view.get(0, 1);
+        let side = view.get(dx, 1);
+        if below.kind == Kind::Empty || below.kind == Kind::Fire {
+            Water::flow(view, 0, 1);
+        } else if side.kind == Kind::Empty || side.kind == Kind::Fire {
+            Water::flow(view, dx, 1);
+        } else if view.get(dx, 0).kind == Kind::Empty {
+            view.swap(dx, 0);
+        } else {
+            view.set(0, 0, current);
+        }
+    }
+}
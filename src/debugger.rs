@@ -0,0 +1,135 @@
+use crate::engine::{Kind, Particle};
+
+/// Where the debugger stops, just before a cell is about to update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Breakpoint {
+    Cell(i32, i32),
+    Material(Kind),
+}
+
+impl Breakpoint {
+    fn hits(&self, x: i32, y: i32, kind: Kind) -> bool {
+        match *self {
+            Breakpoint::Cell(break_x, break_y) => x == break_x && y == break_y,
+            Breakpoint::Material(material) => kind == material,
+        }
+    }
+}
+
+/// A write made by a behavior or script while debugging.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SetCall {
+    /// The cell that was updating when the write happened.
+    pub from: (i32, i32),
+    pub x: i32,
+    pub y: i32,
+    pub kind: Kind,
+    /// The line of the material's script, if the write came from one.
+    pub line: Option<usize>,
+}
+
+/// The cell the debugger stopped at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pause {
+    pub x: i32,
+    pub y: i32,
+    /// The cell and its neighbors, indexed `[d_y + 1][d_x + 1]`.
+    pub neighborhood: [[Particle; 3]; 3],
+    /// `clock` and the material's `state.*` and `global.*` entries.
+    pub variables: Vec<(String, String)>,
+    /// The top-level `let` variables of the material's script, as they will
+    /// be once this cell has updated.
+    pub locals: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    enabled: bool,
+    breakpoints: Vec<Breakpoint>,
+    pub(crate) pause: Option<Pause>,
+}
+
+impl Debugger {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.retain(|other| *other != breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Where the current tick is stopped, if it is.
+    pub fn paused(&self) -> Option<&Pause> {
+        self.pause.as_ref()
+    }
+
+    pub(crate) fn breaks_at(&self, x: i32, y: i32, kind: Kind) -> bool {
+        self.enabled && self.breakpoints.iter().any(|breakpoint| breakpoint.hits(x, y, kind))
+    }
+}
+
+fn entries_to_js(entries: &[(String, String)]) -> js_sys::Object {
+    let object = js_sys::Object::new();
+    for (name, value) in entries {
+        js_sys::Reflect::set(&object, &name.into(), &value.into()).unwrap();
+    }
+    object
+}
+
+impl Pause {
+    /// `{ x, y, neighborhood: [[kind, ...], ...], variables: {...}, locals:
+    /// {...} }`, for the browser.
+    pub fn to_js(&self) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::JsValue;
+        let neighborhood = js_sys::Array::new();
+        for row in &self.neighborhood {
+            let kinds = js_sys::Array::new();
+            for particle in row {
+                kinds.push(&particle.kind.name().into());
+            }
+            neighborhood.push(&kinds);
+        }
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"x".into(), &JsValue::from(self.x)).unwrap();
+        js_sys::Reflect::set(&object, &"y".into(), &JsValue::from(self.y)).unwrap();
+        js_sys::Reflect::set(&object, &"neighborhood".into(), &neighborhood).unwrap();
+        js_sys::Reflect::set(&object, &"variables".into(), &entries_to_js(&self.variables)).unwrap();
+        js_sys::Reflect::set(&object, &"locals".into(), &entries_to_js(&self.locals)).unwrap();
+        object.into()
+    }
+}
+
+impl SetCall {
+    /// `{ from: [x, y], x, y, kind, line }`, for the browser. `line` is
+    /// `null` for writes from native behaviors.
+    pub fn to_js(&self) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::JsValue;
+        let from = js_sys::Array::of2(&JsValue::from(self.from.0), &JsValue::from(self.from.1));
+        let line = self.line.map_or(JsValue::NULL, |line| JsValue::from(line as u32));
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"from".into(), &from).unwrap();
+        js_sys::Reflect::set(&object, &"x".into(), &JsValue::from(self.x)).unwrap();
+        js_sys::Reflect::set(&object, &"y".into(), &JsValue::from(self.y)).unwrap();
+        js_sys::Reflect::set(&object, &"kind".into(), &self.kind.name().into()).unwrap();
+        js_sys::Reflect::set(&object, &"line".into(), &line).unwrap();
+        object.into()
+    }
+}
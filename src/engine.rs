@@ -1,8 +1,23 @@
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::rngs::StdRng;
 use colors_transform::{Rgb, Color as ColorTransform};
 use std::rc::Rc;
-use std::cell::RefCell;
-use crate::scripting::ScriptEngine;
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use rhai::{EvalAltResult, ParseError};
+use crate::scripting::{ScriptEngine, SAND};
+use crate::backend::ScriptBackend;
+use crate::behaviors::Water;
+use crate::compiler;
+use crate::debugger::{Debugger, Pause, SetCall};
+use crate::events::{Event, EventQueue, Hook, Subscriptions, MAX_EVENTS};
+use crate::inspector::Inspection;
+use crate::noise::Noise;
+use crate::tools::{self, Modifiers};
+use crate::plugin::{PluginError, WasmPlugin};
+use crate::render::{FrameBuffer, Overlay};
+use crate::snapshot::{Reader, SnapshotError, Writer};
+use crate::stats::{self, MaterialTimer, TickStats};
 
 static OUT_OF_BOUNDS: Particle = Particle {
     kind: Kind::OutOfBounds,
@@ -17,7 +32,7 @@ static OUT_OF_BOUNDS: Particle = Particle {
     clock: 0,
 };
 
-pub(crate) static EMPTY: Particle = Particle {
+pub static EMPTY: Particle = Particle {
     kind: Kind::Empty,
     extra: Extra {
         color: Color {
@@ -31,6 +46,7 @@ pub(crate) static EMPTY: Particle = Particle {
 };
 
 #[derive(Clone, Eq, PartialEq, Debug, Copy, Hash)]
+#[repr(u8)]
 pub enum Kind {
     Sand = 0,
     Plant = 1,
@@ -41,19 +57,94 @@ pub enum Kind {
 }
 
 impl Kind {
+    pub(crate) const COUNT: usize = 6;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Sand => "sand",
+            Kind::Plant => "plant",
+            Kind::Fire => "fire",
+            Kind::Water => "water",
+            Kind::Empty => "empty",
+            Kind::OutOfBounds => "out of bounds",
+        }
+    }
+
     pub(crate) fn value(&self) -> i32 {
         *self as i32
     }
+
+    /// The kinds that can be painted, in palette order.
+    pub fn materials() -> impl Iterator<Item = Kind> {
+        (0..Kind::COUNT as i32).filter_map(Kind::from_value).filter(|kind| *kind != Kind::OutOfBounds)
+    }
+
+    /// The color new particles of this kind start from.
+    pub fn color(&self) -> Color {
+        match self {
+            Kind::Sand => Color { r: 237, g: 201, b: 175 },
+            Kind::Plant => Color { r: 0, g: 200, b: 0 },
+            Kind::Fire => Color { r: 200, g: 0, b: 0 },
+            Kind::Water => Color { r: 0, g: 0, b: 200 },
+            Kind::Empty | Kind::OutOfBounds => Color { r: 0, g: 0, b: 0 },
+        }
+    }
+
+    /// The character used for this kind in patterns and ASCII worlds.
+    pub fn to_char(&self) -> char {
+        match self {
+            Kind::Sand => 'S',
+            Kind::Plant => 'P',
+            Kind::Fire => 'F',
+            Kind::Water => 'W',
+            Kind::Empty => '.',
+            Kind::OutOfBounds => '#',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Kind> {
+        match c {
+            'S' => Some(Kind::Sand),
+            'P' => Some(Kind::Plant),
+            'F' => Some(Kind::Fire),
+            'W' => Some(Kind::Water),
+            '.' => Some(Kind::Empty),
+            '#' => Some(Kind::OutOfBounds),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_value(value: i32) -> Option<Kind> {
+        match value {
+            0 => Some(Kind::Sand),
+            1 => Some(Kind::Plant),
+            2 => Some(Kind::Fire),
+            3 => Some(Kind::Water),
+            4 => Some(Kind::Empty),
+            5 => Some(Kind::OutOfBounds),
+            _ => None,
+        }
+    }
 }
 
+// Compiled rules read and write particles directly, so the layout must be fixed.
 #[derive(Clone, PartialEq, Debug, Copy)]
+#[repr(C)]
 pub struct Particle {
     pub kind: Kind,
     pub extra: Extra,
-    clock: u8,
+    pub(crate) clock: u8,
 }
 
 impl Particle {
+    pub fn new(kind: Kind) -> Particle {
+        Particle {
+            kind,
+            extra: Extra::from(kind),
+            clock: 0,
+        }
+    }
+
     pub(crate) fn get_kind(&mut self) -> i32 {
         self.kind.value()
     }
@@ -82,6 +173,7 @@ impl Particle {
 }
 
 #[derive(PartialEq, Clone, Copy, Debug, Default)]
+#[repr(C)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -103,48 +195,43 @@ impl Color {
 }
 
 #[derive(PartialEq, Clone, Copy, Debug, Default)]
+#[repr(C)]
 pub struct Extra {
     pub color: Color,
     energy: f32,
 }
 
 impl Extra {
+    pub fn energy(&self) -> f32 {
+        self.energy
+    }
+
     fn from(kind: Kind) -> Extra {
         let mut rng = thread_rng();
         match kind {
             Kind::Sand => {
-                let rgb = Rgb::from(237.0, 201.0, 175.0);
-                let rgb = rgb.lighten(rng.gen_range(-4.0, 4.0));
+                let rgb = kind.color().to_rgb().lighten(rng.gen_range(-4.0, 4.0));
                 Self {
                     color: Color::from_rgb(rgb),
                     energy: 0.0,
                 }
             }
             Kind::Plant => {
-                let rgb = Rgb::from(0.0, 200.0, 0.0);
-                let rgb = rgb.lighten(rng.gen_range(-4.0, 4.0));
-                Self {
-                    color: Color::from_rgb(rgb),
-                    energy: 1.0,
-                }
-            }
-            Kind::Fire => {
-                let rgb = Rgb::from(200.0, 0.0, 0.0);
+                let rgb = kind.color().to_rgb().lighten(rng.gen_range(-4.0, 4.0));
                 Self {
                     color: Color::from_rgb(rgb),
                     energy: 1.0,
                 }
             }
-            Kind::Water => {
-                let rgb = Rgb::from(0.0, 0.0, 200.0);
+            Kind::Fire | Kind::Water => {
                 Self {
-                    color: Color::from_rgb(rgb),
+                    color: kind.color(),
                     energy: 1.0,
                 }
             }
             Kind::Empty | Kind::OutOfBounds => {
                 Self {
-                    color: Color { r: 0, g: 0, b: 0 },
+                    color: kind.color(),
                     energy: 0.0,
                 }
             }
@@ -162,11 +249,31 @@ impl Extra {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct UserEvent {
     pub x: i32,
     pub y: i32,
     pub kind: Kind,
     pub size: u32,
+    /// Where the pointer was on the previous tick, if it was already down.
+    pub previous: Option<(i32, i32)>,
+    pub modifiers: Modifiers,
+    /// The registered tool to use, or `None` for a square of `kind`.
+    pub tool: Option<String>,
+}
+
+impl UserEvent {
+    /// A square brush of `kind` at (x, y).
+    pub fn new(x: i32, y: i32, kind: Kind, size: u32) -> Self {
+        Self {
+            x, y,
+            kind,
+            size,
+            previous: None,
+            modifiers: Modifiers::default(),
+            tool: None,
+        }
+    }
 }
 
 pub struct World {
@@ -174,6 +281,9 @@ pub struct World {
     height: i32,
     data: Vec<Particle>,
     clock: u8,
+    // Writes from behaviors and scripts this tick, kept while debugging.
+    trace: Option<Vec<SetCall>>,
+    events: EventQueue,
 }
 
 impl World {
@@ -184,6 +294,8 @@ impl World {
             height,
             data,
             clock: 0,
+            trace: None,
+            events: EventQueue::default(),
         }
     }
 
@@ -192,7 +304,7 @@ impl World {
     }
 
     fn get_index(&self, x: i32, y: i32) -> usize {
-        return (x + y * self.height) as usize;
+        return (x + y * self.width) as usize;
     }
 
     fn get(&self, x: i32, y: i32) -> Particle {
@@ -211,41 +323,187 @@ impl World {
         self.data[index] = particle;
         self.data[index].clock = self.clock;
     }
+
+    /// `set`, raising events for subscribed materials. Moves only count
+    /// towards contacts.
+    fn write(&mut self, x: i32, y: i32, particle: Particle, moved: bool) {
+        if self.is_out_of_bounds(x, y) {
+            return;
+        }
+        let old = self.get(x, y).kind;
+        self.events.write(x, y, old, particle.kind, moved);
+        self.set(x, y, particle);
+    }
+
+    /// The particles alone, without the trace or pending events, for runs
+    /// whose writes shouldn't count.
+    fn detached(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            trace: None,
+            events: EventQueue::default(),
+            ..*self
+        }
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        let mut events = std::mem::take(&mut self.events);
+        let taken = events.take(|x, y| self.get(x, y).kind);
+        self.events = events;
+        taken
+    }
 }
 
 #[derive(Clone)]
-pub(crate) struct WorldView {
+pub struct WorldView {
     x: i32,
     y: i32,
     world: Rc<RefCell<World>>,
 }
 
 impl WorldView {
-    pub(crate) fn get(&mut self, d_x: i32, d_y: i32) -> Particle {
-        let x = self.x + d_x;
-        let y = self.y + d_y;
+    pub fn get(&mut self, d_x: i32, d_y: i32) -> Particle {
+        match self.offset(d_x, d_y) {
+            Some((x, y)) => self.world.borrow().get(x, y),
+            None => OUT_OF_BOUNDS,
+        }
+    }
 
-        self.world.borrow().get(x, y)
+    pub fn set(&mut self, d_x: i32, d_y: i32, particle: Particle) {
+        self.write(d_x, d_y, particle, None, false);
+    }
+
+    /// `set`, called from script `line` in debug mode.
+    pub(crate) fn set_at(&mut self, line: i32, d_x: i32, d_y: i32, particle: Particle) {
+        self.write(d_x, d_y, particle, Some(line as usize), false);
+    }
+
+    fn write(&mut self, d_x: i32, d_y: i32, particle: Particle, line: Option<usize>, moved: bool) {
+        let (x, y) = match self.offset(d_x, d_y) {
+            Some(position) => position,
+            None => return,
+        };
+
+        let mut world = self.world.borrow_mut();
+        if let Some(trace) = &mut world.trace {
+            trace.push(SetCall {
+                from: (self.x, self.y),
+                x, y,
+                kind: particle.kind,
+                line,
+            });
+        }
+        world.write(x, y, particle, moved);
     }
 
-    pub(crate) fn set(&mut self, d_x: i32, d_y: i32, particle: Particle) {
-        let x = self.x + d_x;
-        let y = self.y + d_y;
+    /// The cell `(d_x, d_y)` away from the center, or `None` when that
+    /// overflows, which is out of bounds for any world.
+    fn offset(&self, d_x: i32, d_y: i32) -> Option<(i32, i32)> {
+        Some((self.x.checked_add(d_x)?, self.y.checked_add(d_y)?))
+    }
+
+    pub(crate) fn get_kind(&mut self, d_x: i32, d_y: i32) -> i32 {
+        self.get(d_x, d_y).kind.value()
+    }
+
+    /// Swaps the particle at the center with its neighbor, unless the
+    /// neighbor is out of bounds.
+    pub fn swap(&mut self, d_x: i32, d_y: i32) {
+        self.swap_with(d_x, d_y, None);
+    }
 
-        self.world.borrow_mut().set(x, y, particle);
+    /// `swap`, called from script `line` in debug mode.
+    pub(crate) fn swap_at(&mut self, line: i32, d_x: i32, d_y: i32) {
+        self.swap_with(d_x, d_y, Some(line as usize));
+    }
+
+    fn swap_with(&mut self, d_x: i32, d_y: i32, line: Option<usize>) {
+        let other = self.get(d_x, d_y);
+        if other.kind == Kind::OutOfBounds {
+            return;
+        }
+
+        let current = self.get(0, 0);
+        self.write(d_x, d_y, current, line, true);
+        self.write(0, 0, other, line, true);
     }
 
     pub(crate) fn set_viewport(&mut self, x: i32, y: i32) {
         self.x = x;
         self.y = y;
     }
+
+    pub(crate) fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub(crate) fn size(&self) -> (i32, i32) {
+        let world = self.world.borrow();
+        (world.width, world.height)
+    }
+
+}
+
+/// A native material rule, run for every cell of its material once per tick.
+///
+/// The view is centered on the cell being updated, so `view.get(0, 0)` is the
+/// particle itself. Native behaviors and scripts can be mixed freely; a
+/// material uses whichever was registered for it last.
+pub trait Behavior<R: Rng = StdRng> {
+    fn update(&self, view: &mut WorldView, rng: &mut R);
+
+    /// The events `on_event` should receive for this material.
+    fn hooks(&self) -> &[Hook] {
+        &[]
+    }
+
+    /// Handles an event for a particle of this material at the end of the
+    /// tick, with the view centered on it.
+    fn on_event(&self, _event: &Event, _view: &mut WorldView, _rng: &mut R) {}
+}
+
+/// How `Sandbox::register_script` runs a script.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScriptRunner {
+    /// Compiled from the restricted rule language, see `compiler::Rule`.
+    Rule,
+    /// Run through Rhai.
+    Rhai,
 }
 
 pub struct Sandbox {
     width: i32,
     height: i32,
     world: Rc<RefCell<World>>,
+    rng: Rc<RefCell<StdRng>>,
+    behaviors: HashMap<Kind, Box<dyn Behavior>>,
     script_engine: ScriptEngine,
+    stats: TickStats,
+    debugger: Debugger,
+    // The tick in progress while the debugger is paused.
+    scan: Option<Scan>,
+    errors: Vec<String>,
+    frame: FrameBuffer,
+}
+
+/// Script errors kept per tick, so a script failing on every cell doesn't
+/// flood them.
+pub const MAX_ERRORS: usize = 16;
+
+fn report(errors: &mut Vec<String>, error: String) {
+    if errors.len() < MAX_ERRORS {
+        errors.push(error);
+    }
+}
+
+struct Scan {
+    column: i32,
+    y: i32,
+    user_events: Vec<UserEvent>,
+    stats: TickStats,
+    errors: Vec<String>,
+    // Milliseconds spent scanning so far, not counting time paused.
+    time: f64,
 }
 
 #[derive(Clone)]
@@ -273,49 +531,566 @@ impl Sandbox {
         }
 
         let world = Rc::new(RefCell::new(world));
+        let rng = Rc::new(RefCell::new(StdRng::from_entropy()));
 
-        let sandbox = Self {
+        let mut sandbox = Self {
             width,
             height,
             world,
-            script_engine: ScriptEngine::new(),
+            rng: rng.clone(),
+            behaviors: HashMap::new(),
+            script_engine: ScriptEngine::new(rng),
+            stats: TickStats::default(),
+            debugger: Debugger::default(),
+            scan: None,
+            errors: Vec::new(),
+            frame: FrameBuffer::new(width, height),
         };
+        sandbox.redraw();
+
+        sandbox.register_script(Kind::Sand, SAND).unwrap();
+        sandbox.register_behavior(Kind::Water, Water);
+        for (name, script) in tools::TOOLS.iter() {
+            sandbox.register_tool(name, script).unwrap();
+        }
 
         sandbox
     }
 
+    /// Builds a sandbox from an ASCII world, one line per row and one
+    /// `Kind::to_char` per cell. Blank lines and surrounding spaces are ignored.
+    pub fn from_ascii(ascii: &str) -> Result<Self, String> {
+        let rows: Vec<&str> = ascii.lines().map(str::trim).filter(|row| !row.is_empty()).collect();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if rows.iter().any(|row| row.chars().count() != width) {
+            return Err("rows must all be the same width".to_string());
+        }
+
+        let mut sandbox = Self::new(width as i32, rows.len() as i32);
+        let mut world = sandbox.world.borrow_mut();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match Kind::from_char(c) {
+                    Some(Kind::OutOfBounds) | None => return Err(format!("unknown material `{}` at {}, {}", c, x, y)),
+                    Some(kind) => world.set(x as i32, y as i32, Particle::new(kind)),
+                }
+            }
+        }
+        drop(world);
+        sandbox.redraw();
+        Ok(sandbox)
+    }
+
+    /// The world in the format `from_ascii` reads.
+    pub fn to_ascii(&self) -> String {
+        (0..self.height)
+            .map(|y| (0..self.width).map(|x| self.get(x, y).kind.to_char()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Reseeds the random number generator shared by behaviors and scripts,
+    /// so the following ticks play out the same way every time.
+    pub fn seed(&mut self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    /// Runs `behavior` natively for every cell of `kind`, replacing any script.
+    pub fn register_behavior(&mut self, kind: Kind, behavior: impl Behavior + 'static) {
+        self.script_engine.remove_script(kind);
+        self.behaviors.insert(kind, Box::new(behavior));
+        self.update_subscriptions();
+    }
+
+    /// Runs `script` for every cell of `kind`, replacing any native behavior,
+    /// and returns how it will run.
+    ///
+    /// Scripts written in the restricted rule language are compiled, see
+    /// `compiler::CompiledRule`, everything else runs through Rhai. While
+    /// debugging, all scripts run through Rhai so their writes can be traced.
+    pub fn register_script(&mut self, kind: Kind, script: &str) -> Result<ScriptRunner, ParseError> {
+        self.script_engine.set_script(kind, script)?;
+        let runner = match compiler::compile(script) {
+            Ok(rule) => {
+                self.behaviors.insert(kind, Box::new(rule));
+                ScriptRunner::Rule
+            }
+            Err(_) => {
+                self.behaviors.remove(&kind);
+                ScriptRunner::Rhai
+            }
+        };
+        self.update_subscriptions();
+        Ok(runner)
+    }
+
+    /// Runs `source` through `backend` for every cell of `kind`, replacing any
+    /// script or native behavior. The backend is shared, so one instance can
+    /// serve several materials.
+    pub fn register_script_with<B: ScriptBackend + 'static>(&mut self, kind: Kind, backend: Rc<RefCell<B>>,
+                                                            source: &str) -> Result<(), B::Error> {
+        self.script_engine.set_foreign_script(kind, backend, source)?;
+        self.behaviors.remove(&kind);
+        self.update_subscriptions();
+        Ok(())
+    }
+
+    /// Runs `script` whenever `hook` fires for a particle of `kind`, in
+    /// addition to its behavior's `on_event`. Besides what material scripts
+    /// see, hooks get an `event` map with `x`, `y` and `kind`, plus
+    /// `replaced_by` for `Destroy` and `dx`, `dy` and `other` for `Contact`.
+    pub fn register_hook(&mut self, kind: Kind, hook: Hook, script: &str) -> Result<(), ParseError> {
+        self.script_engine.set_hook(kind, hook, script)?;
+        self.update_subscriptions();
+        Ok(())
+    }
+
+    fn update_subscriptions(&mut self) {
+        let mut subscriptions = Subscriptions::default();
+        for (index, hooks) in subscriptions.iter_mut().enumerate() {
+            let kind = Kind::from_value(index as i32).unwrap();
+            for (hook, subscribed) in [Hook::Place, Hook::Destroy, Hook::Contact].iter().zip(hooks.iter_mut()) {
+                *subscribed = self.script_engine.has_hook(kind, *hook)
+                    || self.behaviors.get(&kind).map_or(false, |behavior| behavior.hooks().contains(hook));
+            }
+        }
+        self.world.borrow_mut().events.set_subscriptions(subscriptions);
+    }
+
+    /// Delivers the events raised this tick, and any raised while handling
+    /// them, up to `MAX_EVENTS`.
+    fn dispatch_events(&mut self, errors: &mut Vec<String>) {
+        let mut budget = MAX_EVENTS;
+        loop {
+            let events = self.world.borrow_mut().take_events();
+            if events.is_empty() {
+                return;
+            }
+            for event in events {
+                if budget == 0 {
+                    self.world.borrow_mut().events.clear();
+                    return;
+                }
+                budget -= 1;
+
+                let (x, y) = event.position();
+                let mut view = WorldView {
+                    x, y,
+                    world: self.world.clone(),
+                };
+                if let Some(behavior) = self.behaviors.get(&event.kind()) {
+                    behavior.on_event(&event, &mut view, &mut self.rng.borrow_mut());
+                }
+                if let Err(error) = self.script_engine.run_hook(&event, &view) {
+                    report(errors, format!("{} {:?} hook: {}", event.kind().name(), event.hook(), error));
+                }
+            }
+        }
+    }
+
+    /// Makes `script` available as a tool for `UserEvent::tool`, replacing
+    /// any tool with the same name. See `tools` for what it can use.
+    pub fn register_tool(&mut self, name: &str, script: &str) -> Result<(), ParseError> {
+        self.script_engine.set_tool(name, script)
+    }
+
+    /// Runs the pattern rules declared by `script` for every cell of `kind`,
+    /// replacing any script. The script is run once, calling `rule` for each
+    /// pattern:
+    ///
+    /// ```text
+    /// rule(["***", "*S*", "*.*"], ["***", "*.*", "*S*"]);
+    /// rule("*** *S* *S.", "*** *.* *SS", #{ probability: 0.5, mirror_x: true });
+    /// ```
+    pub fn register_patterns(&mut self, kind: Kind, script: &str) -> Result<(), Box<EvalAltResult>> {
+        let rules = self.script_engine.define_patterns(script)?;
+        self.register_behavior(kind, rules);
+        Ok(())
+    }
+
+    /// Runs the wasm plugin in `bytes` for every cell of `kind`, replacing any
+    /// script. See `plugin` for the ABI it must follow.
+    pub fn register_plugin(&mut self, kind: Kind, bytes: &[u8]) -> Result<(), PluginError> {
+        let plugin = WasmPlugin::new(bytes)?;
+        self.register_behavior(kind, plugin);
+        Ok(())
+    }
+
+    /// Replaces the world with one drawn by a generator script, see
+    /// `generators`. The seed drives the script's noise and also reseeds the
+    /// sandbox's random number generator. Nothing changes if the script fails.
+    pub fn generate(&mut self, script: &str, seed: u64) -> Result<(), Box<EvalAltResult>> {
+        let mut generated = World::new(self.width, self.height);
+        generated.clock = self.world.borrow().clock;
+        for index in 0..generated.data.len() {
+            generated.data[index] = Particle { clock: generated.clock, ..EMPTY };
+        }
+        let generated = Rc::new(RefCell::new(generated));
+        let view = WorldView {
+            x: 0, y: 0,
+            world: generated.clone(),
+        };
+
+        // Scripts share the sandbox's generator, so it is reseeded for the run
+        // and put back if the script fails.
+        let previous = std::mem::replace(&mut *self.rng.borrow_mut(), StdRng::seed_from_u64(seed));
+        if let Err(error) = self.script_engine.generate(script, view, Noise::new(seed)) {
+            *self.rng.borrow_mut() = previous;
+            return Err(error);
+        }
+
+        // Copied rather than swapped so `world()` stays valid.
+        let mut world = self.world.borrow_mut();
+        world.data.copy_from_slice(&generated.borrow().data);
+        world.events.clear();
+        drop(world);
+        self.scan = None;
+        self.debugger.pause = None;
+        self.redraw();
+        Ok(())
+    }
+
+    /// Serializes the world and all persistent script state.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let world = self.world.borrow();
+        let mut writer = Writer::new();
+        writer.i32(world.width);
+        writer.i32(world.height);
+        writer.u8(world.clock);
+        for particle in &world.data {
+            writer.u8(particle.kind.value() as u8);
+            writer.u8(particle.extra.color.r);
+            writer.u8(particle.extra.color.g);
+            writer.u8(particle.extra.color.b);
+            writer.f32(particle.extra.energy);
+            writer.u8(particle.clock);
+        }
+        self.script_engine.save_state(&mut writer);
+        writer.finish()
+    }
+
+    /// Replaces the world and script state with a snapshot. Registered
+    /// behaviors and scripts are kept. Nothing changes if the snapshot is invalid.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader::new(bytes)?;
+        let width = reader.i32()?;
+        let height = reader.i32()?;
+        if width <= 0 || height <= 0 {
+            return Err(SnapshotError::Invalid("world must not be empty".to_string()));
+        }
+        let clock = reader.u8()?;
+
+        let size = (width as usize).checked_mul(height as usize)
+            .filter(|size| size.checked_mul(9).map_or(false, |bytes| bytes <= reader.remaining()))
+            .ok_or(SnapshotError::Truncated)?;
+        let mut world = World::new(width, height);
+        world.clock = clock;
+        for index in 0..size {
+            let kind = match Kind::from_value(reader.u8()? as i32) {
+                Some(Kind::OutOfBounds) | None => {
+                    return Err(SnapshotError::Invalid("unknown material".to_string()));
+                }
+                Some(kind) => kind,
+            };
+            let color = Color {
+                r: reader.u8()?,
+                g: reader.u8()?,
+                b: reader.u8()?,
+            };
+            let energy = reader.f32()?;
+            if !(0.0..=1.0).contains(&energy) {
+                return Err(SnapshotError::Invalid("energy out of range".to_string()));
+            }
+            world.data[index] = Particle {
+                kind,
+                extra: Extra { color, energy },
+                clock: reader.u8()?,
+            };
+        }
+
+        let state = ScriptEngine::load_state(&mut reader)?;
+        reader.finish()?;
+
+        self.width = width;
+        self.height = height;
+        world.trace = self.world.borrow_mut().trace.take().map(|_| Vec::new());
+        world.events = EventQueue::new(self.world.borrow().events.subscriptions());
+        *self.world.borrow_mut() = world;
+        self.scan = None;
+        self.debugger.pause = None;
+        self.script_engine.restore_state(state);
+        self.redraw();
+        Ok(())
+    }
+
+    /// Statistics for the last tick.
+    pub fn stats(&self) -> &TickStats {
+        &self.stats
+    }
+
+    /// Errors raised by scripts, tools and hooks during the last tick, up to
+    /// `MAX_ERRORS`. Failing scripts stop for that cell, the tick goes on.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Particle {
+        self.world.borrow().get(x, y)
+    }
+
+    /// What the cell at `(x, y)` holds, or `None` outside the world.
+    pub fn inspect(&self, x: i32, y: i32) -> Option<Inspection> {
+        let world = self.world.borrow();
+        if x < 0 || x >= world.width || y < 0 || y >= world.height {
+            return None;
+        }
+        Some(Inspection::new(x, y, &world.get(x, y), world.clock))
+    }
+
     pub fn world(&self) -> *const Particle {
         self.world.borrow().data.as_ptr()
     }
 
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Every particle, row by row.
+    pub fn particles(&self) -> Ref<'_, [Particle]> {
+        Ref::map(self.world.borrow(), |world| &world.data[..])
+    }
+
+    /// The world as RGBA, kept up to date by ticks, which only redraw the
+    /// cells they write. The buffer moves when the world is resized.
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.frame
+    }
+
+    /// Changes what `frame` shows.
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.frame.set_overlay(overlay);
+        self.redraw();
+    }
+
+    /// Turns lighting on or off for `frame`, see `FrameBuffer::set_lighting`.
+    pub fn set_lighting(&mut self, lighting: bool) {
+        self.frame.set_lighting(lighting);
+        self.redraw();
+    }
+
+    /// Goes up by one every tick, wrapping around.
+    pub fn clock(&self) -> u8 {
+        self.world.borrow().clock
+    }
+
+    fn redraw(&mut self) {
+        let world = self.world.borrow();
+        self.frame.resize(world.width, world.height);
+        self.frame.draw(&world.data, world.clock);
+    }
+
+    fn redraw_written(&mut self) {
+        let world = self.world.borrow();
+        self.frame.draw_written(&world.data, world.clock);
+    }
+
+    /// Turns debug mode on or off. While debugging, ticks stop at
+    /// breakpoints and every write is recorded in `trace`. Turning it off
+    /// while paused finishes the tick.
+    pub fn set_debugging(&mut self, debugging: bool) {
+        self.debugger.set_enabled(debugging);
+        self.script_engine.set_debug(debugging);
+        self.world.borrow_mut().trace = if debugging { Some(Vec::new()) } else { None };
+        // Ticks wait while the debugger is paused, and with it off nothing
+        // would resume them, so the paused tick runs to the end.
+        if !debugging && self.scan.is_some() {
+            self.scan(false);
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Writes made by behaviors and scripts so far this tick, while debugging.
+    pub fn trace(&self) -> Vec<SetCall> {
+        self.world.borrow().trace.clone().unwrap_or_default()
+    }
+
+    /// Updates the cell the debugger is paused at and stops again before the
+    /// next one. If the debugger isn't paused, starts a tick and stops before
+    /// its first update.
+    pub fn step(&mut self) {
+        if self.scan.is_none() {
+            self.start_tick(Vec::new());
+        }
+        self.scan(true);
+    }
+
+    /// Finishes the paused tick, unless another breakpoint is hit.
+    pub fn resume(&mut self) {
+        if self.scan.is_some() {
+            self.scan(false);
+        }
+    }
+
+    /// Runs one tick. Does nothing while the debugger is paused.
     pub fn tick(&mut self, user_event: Option<UserEvent>) {
-        let (clock, _) = self.world.borrow().clock.overflowing_add(1);
-        self.world.borrow_mut().clock = clock;
+        self.tick_with(user_event.into_iter().collect());
+    }
+
+    /// Runs one tick with any number of events, such as one per finger on a
+    /// touch screen. They apply in order once the world has updated.
+    pub fn tick_with(&mut self, user_events: Vec<UserEvent>) {
+        if self.scan.is_some() {
+            return;
+        }
+        self.start_tick(user_events);
+        self.scan(false);
+    }
+
+    fn start_tick(&mut self, user_events: Vec<UserEvent>) {
+        let mut world = self.world.borrow_mut();
+        let (clock, _) = world.clock.overflowing_add(1);
+        world.clock = clock;
+        if let Some(trace) = &mut world.trace {
+            trace.clear();
+        }
+
+        self.script_engine.set_clock(clock);
+        self.scan = Some(Scan {
+            column: 0,
+            y: 0,
+            user_events,
+            stats: TickStats::default(),
+            errors: Vec::new(),
+            time: 0.0,
+        });
+    }
+
+    fn pause_at(&mut self, x: i32, y: i32, current: Particle) -> Pause {
+        let mut view = WorldView {
+            x, y,
+            world: self.world.clone(),
+        };
+        let mut neighborhood = [[EMPTY; 3]; 3];
+        for d_y in -1..=1 {
+            for d_x in -1..=1 {
+                neighborhood[(d_y + 1) as usize][(d_x + 1) as usize] = view.get(d_x, d_y);
+            }
+        }
+        let detached = WorldView {
+            x, y,
+            world: Rc::new(RefCell::new(self.world.borrow().detached())),
+        };
+        Pause {
+            x, y,
+            neighborhood,
+            variables: self.script_engine.variables(current.kind),
+            locals: self.script_engine.locals(current, &detached),
+        }
+    }
+
+    /// Continues the tick in progress. With `step`, stops before the next
+    /// cell that updates, otherwise only at breakpoints.
+    fn scan(&mut self, step: bool) {
+        let start = stats::now();
+        let mut scan = self.scan.take().unwrap();
+        let clock = self.world.borrow().clock;
+        // The cell the debugger paused at runs rather than stopping again.
+        let mut resuming = self.debugger.pause.take().is_some();
+        let scripted = self.debugger.is_enabled();
+        let mut timer = MaterialTimer::default();
 
         let mut view = WorldView {
             x: 0, y: 0,
             world: self.world.clone(),
         };
 
-        self.script_engine.tick(clock, self.width, self.height, view).unwrap();
-
-        match user_event {
-            Some(event) => {
-                let size = event.size as i32;
-                for x in -size..=size {
-                    for y in -size..=size {
-                        let x = x + event.x;
-                        let y = y + event.y;
-
-                        self.world.borrow_mut().set(x, y, Particle {
-                            kind: event.kind,
-                            extra: Extra::from(event.kind),
-                            clock,
-                        });
+        while scan.column < self.width {
+            let x = if clock % 2 == 0 {
+                self.width - (1 + scan.column)
+            } else {
+                scan.column
+            };
+
+            while scan.y < self.height {
+                let y = scan.y;
+                let current = self.world.borrow().get(x, y);
+                if current.kind == Kind::Empty || current.clock == clock {
+                    scan.y += 1;
+                    continue;
+                }
+
+                if !resuming && (step || self.debugger.breaks_at(x, y, current.kind)) {
+                    timer.stop(&mut scan.stats);
+                    self.debugger.pause = Some(self.pause_at(x, y, current));
+                    scan.time += stats::now() - start;
+                    self.scan = Some(scan);
+                    self.redraw_written();
+                    return;
+                }
+                resuming = false;
+
+                view.set_viewport(x, y);
+                timer.start(current.kind, &mut scan.stats);
+                match self.behaviors.get(&current.kind) {
+                    Some(behavior) if !(scripted && self.script_engine.has_script(current.kind)) => {
+                        behavior.update(&mut view, &mut self.rng.borrow_mut())
+                    }
+                    _ => if let Err(error) = self.script_engine.run(current, &view) {
+                        report(&mut scan.errors, format!("{} script: {}", current.kind.name(), error));
+                    }
+                }
+                scan.stats.count(current.kind);
+                scan.y += 1;
+            }
+
+            scan.y = 0;
+            scan.column += 1;
+        }
+
+        timer.stop(&mut scan.stats);
+
+        for event in &scan.user_events {
+            match event.tool {
+                Some(ref tool) => {
+                    view.set_viewport(event.x, event.y);
+                    if let Err(error) = self.script_engine.run_tool(tool, event, &view) {
+                        report(&mut scan.errors, format!("tool `{}`: {}", tool, error));
+                    }
+                }
+                None => {
+                    let size = event.size as i32;
+                    for x in -size..=size {
+                        for y in -size..=size {
+                            let x = x + event.x;
+                            let y = y + event.y;
+
+                            self.world.borrow_mut().write(x, y, Particle {
+                                kind: event.kind,
+                                extra: Extra::from(event.kind),
+                                clock,
+                            }, false);
+                        }
                     }
                 }
             }
-            None => {}
         }
+
+        self.dispatch_events(&mut scan.errors);
+        self.redraw_written();
+
+        scan.stats.time = scan.time + stats::now() - start;
+        self.stats = scan.stats;
+        self.errors = scan.errors;
     }
 }
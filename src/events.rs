@@ -0,0 +1,193 @@
+//! Events raised by writes to the world, handled at the end of each tick by
+//! the behaviors and hook scripts of the materials involved.
+
+use std::collections::HashSet;
+use rhai::{Dynamic, Map};
+use crate::engine::Kind;
+
+/// Handlers can raise more events, up to this many per tick.
+pub const MAX_EVENTS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hook {
+    Place,
+    Destroy,
+    Contact,
+}
+
+impl Hook {
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// A particle of `kind` was created at (x, y) by the brush, a tool, a
+    /// behavior or a script. Swaps move particles rather than create them.
+    Place { x: i32, y: i32, kind: Kind },
+    /// The particle of `kind` at (x, y) was replaced by one of `replaced_by`.
+    Destroy { x: i32, y: i32, kind: Kind, replaced_by: Kind },
+    /// The particle of `kind` at (x, y) started touching one of `other` at
+    /// (x + d_x, y + d_y).
+    Contact { x: i32, y: i32, kind: Kind, d_x: i32, d_y: i32, other: Kind },
+}
+
+impl Event {
+    pub fn hook(&self) -> Hook {
+        match self {
+            Event::Place { .. } => Hook::Place,
+            Event::Destroy { .. } => Hook::Destroy,
+            Event::Contact { .. } => Hook::Contact,
+        }
+    }
+
+    /// The material the event is delivered to.
+    pub fn kind(&self) -> Kind {
+        match *self {
+            Event::Place { kind, .. } | Event::Destroy { kind, .. } | Event::Contact { kind, .. } => kind,
+        }
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        match *self {
+            Event::Place { x, y, .. } | Event::Destroy { x, y, .. } | Event::Contact { x, y, .. } => (x, y),
+        }
+    }
+
+    /// The `event` map hook scripts see.
+    pub(crate) fn to_map(self) -> Map {
+        let mut map = Map::new();
+        let (x, y) = self.position();
+        map.insert("x".to_string(), Dynamic::from(x));
+        map.insert("y".to_string(), Dynamic::from(y));
+        map.insert("kind".to_string(), Dynamic::from(self.kind().value()));
+        match self {
+            Event::Place { .. } => {}
+            Event::Destroy { replaced_by, .. } => {
+                map.insert("replaced_by".to_string(), Dynamic::from(replaced_by.value()));
+            }
+            Event::Contact { d_x, d_y, other, .. } => {
+                map.insert("dx".to_string(), Dynamic::from(d_x));
+                map.insert("dy".to_string(), Dynamic::from(d_y));
+                map.insert("other".to_string(), Dynamic::from(other.value()));
+            }
+        }
+        map
+    }
+}
+
+pub(crate) type Subscriptions = [[bool; 3]; Kind::COUNT];
+
+type Touching = ((i32, i32, Kind), (i32, i32, Kind));
+
+const NEIGHBORS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+fn is_material(kind: Kind) -> bool {
+    kind != Kind::Empty && kind != Kind::OutOfBounds
+}
+
+/// Events waiting for the end of the tick. Only events some material
+/// subscribes to are kept.
+#[derive(Default)]
+pub(crate) struct EventQueue {
+    subscriptions: Subscriptions,
+    contacts: bool,
+    events: Vec<Event>,
+    // Cells written since contacts were last looked for.
+    written: Vec<(i32, i32)>,
+    touching: HashSet<Touching>,
+}
+
+impl EventQueue {
+    pub(crate) fn new(subscriptions: Subscriptions) -> Self {
+        let mut queue = Self::default();
+        queue.set_subscriptions(subscriptions);
+        queue
+    }
+
+    pub(crate) fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions
+    }
+
+    pub(crate) fn set_subscriptions(&mut self, subscriptions: Subscriptions) {
+        self.subscriptions = subscriptions;
+        self.contacts = subscriptions.iter().any(|hooks| hooks[Hook::Contact.index()]);
+        if !self.contacts {
+            self.written.clear();
+            self.touching.clear();
+        }
+    }
+
+    fn subscribes(&self, kind: Kind, hook: Hook) -> bool {
+        self.subscriptions[kind.value() as usize][hook.index()]
+    }
+
+    /// Records a write replacing `old` with `new` at (x, y).
+    pub(crate) fn write(&mut self, x: i32, y: i32, old: Kind, new: Kind, moved: bool) {
+        if old != new && !moved {
+            if self.subscribes(new, Hook::Place) {
+                self.events.push(Event::Place { x, y, kind: new });
+            }
+            if self.subscribes(old, Hook::Destroy) {
+                self.events.push(Event::Destroy { x, y, kind: old, replaced_by: new });
+            }
+        }
+        if self.contacts {
+            self.written.push((x, y));
+        }
+    }
+
+    /// Takes the queued events, plus contacts made by the cells written since
+    /// the last call. `kind_at` reads the world as it is now.
+    pub(crate) fn take(&mut self, kind_at: impl Fn(i32, i32) -> Kind) -> Vec<Event> {
+        if !self.written.is_empty() {
+            self.touching.retain(|&((x0, y0, kind0), (x1, y1, kind1))| {
+                kind_at(x0, y0) == kind0 && kind_at(x1, y1) == kind1
+            });
+
+            let mut written = std::mem::take(&mut self.written);
+            written.sort_unstable();
+            written.dedup();
+            for (x, y) in written {
+                let kind = kind_at(x, y);
+                if !is_material(kind) {
+                    continue;
+                }
+                for &(d_x, d_y) in &NEIGHBORS {
+                    let other = kind_at(x + d_x, y + d_y);
+                    if !is_material(other) || other == kind {
+                        continue;
+                    }
+                    if !self.subscribes(kind, Hook::Contact) && !self.subscribes(other, Hook::Contact) {
+                        continue;
+                    }
+                    let here = (x, y, kind);
+                    let there = (x + d_x, y + d_y, other);
+                    let pair = if (x, y) < (x + d_x, y + d_y) { (here, there) } else { (there, here) };
+                    if !self.touching.insert(pair) {
+                        continue;
+                    }
+                    if self.subscribes(kind, Hook::Contact) {
+                        self.events.push(Event::Contact { x, y, kind, d_x, d_y, other });
+                    }
+                    if self.subscribes(other, Hook::Contact) {
+                        self.events.push(Event::Contact {
+                            x: x + d_x, y: y + d_y,
+                            kind: other,
+                            d_x: -d_x, d_y: -d_y,
+                            other: kind,
+                        });
+                    }
+                }
+            }
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+        self.written.clear();
+        self.touching.clear();
+    }
+}
@@ -0,0 +1,181 @@
+//! Scripts that draw a new world, run once by `Sandbox::generate`.
+//!
+//! Generators see the whole, empty world as `world`, with `world.width`,
+//! `world.height`, `world.kind(x, y)`, `world.set(x, y, kind)`,
+//! `world.fill(x0, y0, x1, y1, kind)` for rectangles and
+//! `world.line(x0, y0, x1, y1, kind)`. Kinds are the `KIND_*` constants.
+//! `noise.value(x, y)`, `noise.perlin(x, y)` and `noise.simplex(x, y)` give
+//! seeded noise in `[-1, 1]`, and `rng` is seeded the same way.
+
+use rhai::{Engine, RegisterFn};
+use crate::engine::{Kind, Particle, WorldView};
+use crate::noise::Noise;
+
+pub static TERRAIN: &str = r"
+    let sea = world.height * 2 / 3;
+    for x in range(0, world.width) {
+        let hills = noise.perlin(x.to_float() / 60.0, 0.5) + noise.value(x.to_float() / 12.0, 0.5) * 0.2;
+        let ground = world.height / 2 + (hills * world.height.to_float() / 4.0).to_int();
+        world.fill(x, ground, x, world.height - 1, KIND_SAND);
+        if ground > sea {
+            world.fill(x, sea, x, ground - 1, KIND_WATER);
+        } else if rng.gen_bool(0.03) {
+            world.line(x, ground - 1, x, ground - 8, KIND_PLANT);
+        }
+    }
+";
+
+pub static CAVES: &str = r"
+    world.fill(0, 0, world.width - 1, world.height - 1, KIND_PLANT);
+    for y in range(0, world.height) {
+        for x in range(0, world.width) {
+            let cave = noise.simplex(x.to_float() / 32.0, y.to_float() / 32.0)
+                     + noise.perlin(x.to_float() / 8.0, y.to_float() / 8.0) * 0.3;
+            if cave > 0.25 {
+                world.set(x, y, KIND_EMPTY);
+            }
+        }
+    }
+    for x in range(0, world.width) {
+        for y in range(world.height * 3 / 4, world.height) {
+            if world.kind(x, y) == KIND_EMPTY {
+                world.set(x, y, KIND_WATER);
+            }
+        }
+    }
+";
+
+pub static ISLANDS: &str = r"
+    let sea = world.height / 2;
+    world.fill(0, sea, world.width - 1, world.height - 1, KIND_WATER);
+    for x in range(0, world.width) {
+        let land = noise.perlin(x.to_float() / 40.0, 7.5) + noise.value(x.to_float() / 10.0, 3.5) * 0.25;
+        if land > 0.15 {
+            let top = sea - ((land - 0.15) * 60.0).to_int();
+            world.fill(x, top, x, world.height - 1, KIND_SAND);
+            if rng.gen_bool(0.1) {
+                world.set(x, top - 1, KIND_PLANT);
+            }
+        }
+    }
+";
+
+/// The built-in generators, by name.
+pub static GENERATORS: [(&str, &str); 3] = [
+    ("terrain", TERRAIN),
+    ("caves", CAVES),
+    ("islands", ISLANDS),
+];
+
+pub fn builtin(name: &str) -> Option<&'static str> {
+    GENERATORS.iter().find(|(other, _)| *other == name).map(|(_, script)| *script)
+}
+
+/// The world as seen by a generator, in absolute coordinates.
+#[derive(Clone)]
+pub(crate) struct GeneratorWorld(pub(crate) WorldView);
+
+impl GeneratorWorld {
+    fn width(&mut self) -> i32 {
+        self.0.size().0
+    }
+
+    fn height(&mut self) -> i32 {
+        self.0.size().1
+    }
+
+    fn kind(&mut self, x: i32, y: i32) -> i32 {
+        self.0.get_kind(x, y)
+    }
+
+    fn set(&mut self, x: i32, y: i32, kind: i32) {
+        match Kind::from_value(kind) {
+            Some(Kind::OutOfBounds) | None => {}
+            Some(kind) => self.0.set(x, y, Particle::new(kind)),
+        }
+    }
+
+    fn fill(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, kind: i32) {
+        let (width, height) = self.0.size();
+        for y in y0.min(y1).max(0)..=y0.max(y1).min(height - 1) {
+            for x in x0.min(x1).max(0)..=x0.max(x1).min(width - 1) {
+                self.set(x, y, kind);
+            }
+        }
+    }
+
+    fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, kind: i32) {
+        let (width, height) = self.0.size();
+        let ((x0, y0), (x1, y1)) = match clip(x0, y0, x1, y1, width, height) {
+            Some(ends) => ends,
+            None => return,
+        };
+        let (d_x, d_y) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y, mut error) = (x0, y0, d_x + d_y);
+        loop {
+            self.set(x as i32, y as i32, kind);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= d_y {
+                error += d_y;
+                x += step_x;
+            }
+            if doubled <= d_x {
+                error += d_x;
+                y += step_y;
+            }
+        }
+    }
+}
+
+/// The part of the line from `(x0, y0)` to `(x1, y1)` inside a `width` by
+/// `height` world, or `None` if it misses the world, so far away ends don't
+/// take billions of steps. Widened to `i64` so their deltas can't overflow.
+fn clip(x0: i32, y0: i32, x1: i32, y1: i32, width: i32, height: i32) -> Option<((i64, i64), (i64, i64))> {
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+    let (d_x, d_y) = (x1 - x0, y1 - y0);
+    let (max_x, max_y) = ((width - 1) as f64, (height - 1) as f64);
+    let (mut enter, mut exit) = (0.0f64, 1.0f64);
+    // Liang-Barsky: each edge is `p * t <= q` along the line.
+    for &(p, q) in &[(-d_x, x0), (d_x, max_x - x0), (-d_y, y0), (d_y, max_y - y0)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+    let at = |t: f64| {
+        let x = (x0 + t * d_x).round().max(0.0).min(max_x);
+        let y = (y0 + t * d_y).round().max(0.0).min(max_y);
+        (x as i64, y as i64)
+    };
+    Some((at(enter), at(exit)))
+}
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_type::<GeneratorWorld>();
+    engine.register_get("width", GeneratorWorld::width);
+    engine.register_get("height", GeneratorWorld::height);
+    engine.register_fn("kind", GeneratorWorld::kind);
+    engine.register_fn("set", GeneratorWorld::set);
+    engine.register_fn("fill", GeneratorWorld::fill);
+    engine.register_fn("line", GeneratorWorld::line);
+
+    engine.register_type::<Noise>();
+    engine.register_fn("value", |noise: &mut Noise, x: f64, y: f64| noise.value(x, y));
+    engine.register_fn("perlin", |noise: &mut Noise, x: f64, y: f64| noise.perlin(x, y));
+    engine.register_fn("simplex", |noise: &mut Noise, x: f64, y: f64| noise.simplex(x, y));
+}
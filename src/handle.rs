@@ -0,0 +1,98 @@
+//! A sandbox for JS apps that bring their own page, canvas and input,
+//! unlike `run`, which sets up all of those.
+
+use wasm_bindgen::prelude::*;
+use crate::engine::{Kind, Sandbox, UserEvent};
+
+#[wasm_bindgen]
+pub struct SandboxHandle {
+    sandbox: Sandbox,
+    /// Painted since the last tick.
+    events: Vec<UserEvent>,
+}
+
+#[wasm_bindgen]
+impl SandboxHandle {
+    /// An empty world, with randomness drawn from `seed`. Throws unless both
+    /// sides are positive.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: i32, height: i32, seed: u32) -> Result<SandboxHandle, JsValue> {
+        if width <= 0 || height <= 0 {
+            return Err(JsValue::from(format!("a world can't be {} by {}", width, height)));
+        }
+        let mut sandbox = Sandbox::new(width, height);
+        sandbox.seed(seed as u64);
+        Ok(Self { sandbox, events: Vec::new() })
+    }
+
+    pub fn width(&self) -> i32 {
+        self.sandbox.width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.sandbox.height()
+    }
+
+    /// Runs one tick, applying what was painted since the last one.
+    pub fn tick(&mut self) {
+        self.sandbox.tick_with(self.events.drain(..).collect());
+    }
+
+    /// Paints a square of the named material, `size` cells to each side of
+    /// `(x, y)`, on the next tick.
+    pub fn paint(&mut self, x: i32, y: i32, material: &str, size: u32) -> Result<(), JsValue> {
+        let kind = Kind::materials().find(|kind| kind.name() == material)
+            .ok_or_else(|| JsValue::from(format!("unknown material `{}`", material)))?;
+        self.events.push(UserEvent::new(x, y, kind, size));
+        Ok(())
+    }
+
+    /// What the cell at `(x, y)` holds, see `Inspection::to_js`, or `null`
+    /// outside the world.
+    pub fn inspect(&self, x: i32, y: i32) -> JsValue {
+        self.sandbox.inspect(x, y).map_or(JsValue::NULL, |inspection| inspection.to_js())
+    }
+
+    /// The world as bytes for `restore`.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.sandbox.snapshot()
+    }
+
+    /// Replaces the world with a snapshot, which may change its size.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.sandbox.restore(bytes).map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    /// Statistics for the last tick, see `TickStats::to_js`.
+    pub fn stats(&self) -> JsValue {
+        self.sandbox.stats().to_js()
+    }
+
+    /// Script errors from the last tick.
+    pub fn errors(&self) -> js_sys::Array {
+        self.sandbox.errors().iter().map(|error| JsValue::from(error.as_str())).collect()
+    }
+
+    /// The address of the world's RGBA buffer in wasm memory, `width` by
+    /// `height` pixels, see `Sandbox::frame`. It moves when the world is
+    /// resized, so ask again after `restore`.
+    pub fn frame_ptr(&self) -> *const u8 {
+        self.sandbox.frame().data().as_ptr()
+    }
+
+    /// The length in bytes of the RGBA buffer.
+    pub fn frame_len(&self) -> usize {
+        self.sandbox.frame().data().len()
+    }
+
+    /// The wasm memory the buffer lives in.
+    pub fn memory(&self) -> JsValue {
+        wasm_bindgen::memory()
+    }
+}
+
+impl SandboxHandle {
+    pub fn sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+}
@@ -0,0 +1,205 @@
+//! Pointers on the canvas, whether mice, pens or fingers, turned into brush
+//! events and camera moves. Positions are in screen pixels.
+
+use std::collections::BTreeMap;
+use crate::engine::UserEvent;
+use crate::render::Camera;
+use crate::tools::Modifiers;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PointerKind {
+    Mouse,
+    Pen,
+    Touch,
+}
+
+impl PointerKind {
+    /// From a Pointer Events `pointerType`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "pen" => PointerKind::Pen,
+            "touch" => PointerKind::Touch,
+            _ => PointerKind::Mouse,
+        }
+    }
+}
+
+/// Where a pointer is and what it holds, from a pointer event.
+#[derive(Copy, Clone, Debug)]
+pub struct PointerInput {
+    pub id: i32,
+    pub kind: PointerKind,
+    pub x: i32,
+    pub y: i32,
+    /// From 0 to 1, where 0.5 is the usual for devices without pressure.
+    pub pressure: f32,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Pointer {
+    kind: PointerKind,
+    x: i32,
+    y: i32,
+    painting: bool,
+    panning: bool,
+    pressure: f32,
+    modifiers: Modifiers,
+    /// The cell this pointer painted at on the last tick.
+    previous: Option<(i32, i32)>,
+}
+
+/// Two fingers zooming and panning the camera: how far apart they were and
+/// the zoom when they touched, and where the point between them last was.
+#[derive(Copy, Clone, Debug)]
+struct Pinch {
+    distance: f64,
+    zoom: i32,
+    center: (i32, i32),
+}
+
+/// The pointers that are down, each painting on its own, until two fingers
+/// touch and pinch instead.
+#[derive(Default)]
+pub struct Pointers {
+    pointers: BTreeMap<i32, Pointer>,
+    pinch: Option<Pinch>,
+    hovered: (i32, i32),
+    hovering: bool,
+}
+
+impl Pointers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where a pointer was last seen, down or not.
+    pub fn hovered(&self) -> (i32, i32) {
+        self.hovered
+    }
+
+    /// Whether a pointer is over the canvas.
+    pub fn hovering(&self) -> bool {
+        self.hovering
+    }
+
+    /// A pointer left the canvas.
+    pub fn left(&mut self) {
+        self.hovering = false;
+    }
+
+    /// A pointer went down. Mouse buttons other than the left one drag the
+    /// camera.
+    pub fn down(&mut self, input: PointerInput, button: i16, camera: &Camera) {
+        let panning = input.kind == PointerKind::Mouse && button != 0;
+        self.pointers.insert(input.id, Pointer {
+            kind: input.kind,
+            x: input.x,
+            y: input.y,
+            painting: !panning,
+            panning,
+            pressure: input.pressure,
+            modifiers: input.modifiers,
+            previous: None,
+        });
+        self.hovered = (input.x, input.y);
+        self.hovering = true;
+
+        let touches = self.touches();
+        if touches.len() >= 2 {
+            for pointer in self.pointers.values_mut().filter(|pointer| pointer.kind == PointerKind::Touch) {
+                pointer.painting = false;
+            }
+            if self.pinch.is_none() {
+                let (center, distance) = span(touches[0], touches[1]);
+                self.pinch = Some(Pinch { distance, zoom: camera.zoom, center });
+            }
+        }
+    }
+
+    /// A pointer moved, whether it is down or just hovering.
+    pub fn moved(&mut self, input: PointerInput, camera: &mut Camera) {
+        self.hovered = (input.x, input.y);
+        self.hovering = true;
+        let pointer = match self.pointers.get_mut(&input.id) {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        if pointer.panning {
+            camera.pan(input.x - pointer.x, input.y - pointer.y);
+        }
+        pointer.x = input.x;
+        pointer.y = input.y;
+        pointer.pressure = input.pressure;
+        pointer.modifiers = input.modifiers;
+
+        if pointer.kind == PointerKind::Touch {
+            self.pinch_to(camera);
+        }
+    }
+
+    /// A pointer went up or was cancelled.
+    pub fn up(&mut self, id: i32) {
+        self.pointers.remove(&id);
+        if self.touches().len() < 2 {
+            self.pinch = None;
+        }
+    }
+
+    /// Brush events for the pointers that are painting inside the world.
+    /// `brush` gives everything but the position, and a pen's pressure
+    /// scales its size, with the usual half pressure leaving it as is.
+    pub fn events(&mut self, camera: &Camera, width: i32, height: i32, brush: &UserEvent) -> Vec<UserEvent> {
+        let mut events = Vec::new();
+        for pointer in self.pointers.values_mut() {
+            let (x, y) = camera.to_world(pointer.x, pointer.y);
+            if !pointer.painting || x < 0 || x >= width || y < 0 || y >= height {
+                pointer.previous = None;
+                continue;
+            }
+            let size = match pointer.kind {
+                PointerKind::Pen => ((brush.size as f32 * pointer.pressure * 2.0).round() as u32).max(1),
+                _ => brush.size,
+            };
+            events.push(UserEvent {
+                x,
+                y,
+                size,
+                previous: pointer.previous,
+                modifiers: pointer.modifiers,
+                ..brush.clone()
+            });
+            pointer.previous = Some((x, y));
+        }
+        events
+    }
+
+    fn touches(&self) -> Vec<(i32, i32)> {
+        self.pointers.values()
+            .filter(|pointer| pointer.kind == PointerKind::Touch)
+            .map(|pointer| (pointer.x, pointer.y))
+            .collect()
+    }
+
+    /// Follows the first two fingers: moving them together pans, spreading
+    /// them apart zooms in whole steps around the point between them.
+    fn pinch_to(&mut self, camera: &mut Camera) {
+        let touches = self.touches();
+        let pinch = match &mut self.pinch {
+            Some(pinch) if touches.len() >= 2 => pinch,
+            _ => return,
+        };
+        let (center, distance) = span(touches[0], touches[1]);
+        camera.pan(center.0 - pinch.center.0, center.1 - pinch.center.1);
+        let zoom = (pinch.zoom as f64 * distance / pinch.distance.max(1.0)).round() as i32;
+        camera.zoom_at(center.0, center.1, zoom - camera.zoom);
+        pinch.center = center;
+    }
+}
+
+/// The point between two positions and the distance between them.
+fn span(a: (i32, i32), b: (i32, i32)) -> ((i32, i32), f64) {
+    let center = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+    let distance = (((a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)) as f64).sqrt();
+    (center, distance)
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+use crate::engine::{Color, Kind, Particle};
+use crate::render::FIELDS;
+
+/// What a cell holds, see `Sandbox::inspect`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inspection {
+    pub x: i32,
+    pub y: i32,
+    pub kind: Kind,
+    pub energy: f32,
+    pub color: Color,
+    /// The tick the cell was last written at.
+    pub clock: u8,
+    /// Every field overlays can show, by name.
+    pub fields: Vec<(&'static str, f32)>,
+}
+
+impl Inspection {
+    pub(crate) fn new(x: i32, y: i32, particle: &Particle, clock: u8) -> Self {
+        Self {
+            x,
+            y,
+            kind: particle.kind,
+            energy: particle.extra.energy(),
+            color: particle.extra.color,
+            clock: particle.clock,
+            fields: FIELDS.iter().map(|field| (field.name, (field.value)(particle, clock))).collect(),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<f32> {
+        self.fields.iter().find(|(field, _)| *field == name).map(|(_, value)| *value)
+    }
+
+    /// `{ x, y, material, energy, color, clock, fields: { energy, ... } }`,
+    /// for the browser, with a CSS color.
+    pub fn to_js(&self) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::JsValue;
+        let fields = js_sys::Object::new();
+        for (name, value) in &self.fields {
+            js_sys::Reflect::set(&fields, &(*name).into(), &JsValue::from(*value)).unwrap();
+        }
+        let Color { r, g, b } = self.color;
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"x".into(), &JsValue::from(self.x)).unwrap();
+        js_sys::Reflect::set(&object, &"y".into(), &JsValue::from(self.y)).unwrap();
+        js_sys::Reflect::set(&object, &"material".into(), &self.kind.name().into()).unwrap();
+        js_sys::Reflect::set(&object, &"energy".into(), &JsValue::from(self.energy)).unwrap();
+        js_sys::Reflect::set(&object, &"color".into(), &format!("rgb({}, {}, {})", r, g, b).into()).unwrap();
+        js_sys::Reflect::set(&object, &"clock".into(), &JsValue::from(self.clock)).unwrap();
+        js_sys::Reflect::set(&object, &"fields".into(), &fields).unwrap();
+        object.into()
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Color { r, g, b } = self.color;
+        write!(f, "{} at ({}, {}): color #{:02x}{:02x}{:02x}, clock {}",
+               self.kind.name(), self.x, self.y, r, g, b, self.clock)?;
+        for (name, value) in &self.fields {
+            write!(f, ", {} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -1,52 +1,353 @@
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::{JsCast, Clamped};
+use wasm_bindgen::JsCast;
 use std::cell::{RefCell, Cell};
 use std::rc::Rc;
-use crate::engine::{Kind, UserEvent, Sandbox};
-
+use crate::debugger::Breakpoint;
+use crate::engine::{Color, Kind, UserEvent, Sandbox};
+use crate::input::{PointerInput, PointerKind, Pointers};
+use crate::render::{BrushPreview, Camera, Overlay, Renderer};
+use crate::tools::Modifiers;
+
+pub mod backend;
+pub mod behaviors;
+pub mod compiler;
+pub mod debugger;
 pub mod engine;
+pub mod events;
+pub mod generators;
+pub mod handle;
+pub mod input;
+pub mod inspector;
+pub mod lighting;
+pub mod noise;
+pub mod patterns;
+pub mod plugin;
+pub mod render;
 pub mod scripting;
-
-struct Renderer {
+pub mod snapshot;
+pub mod stats;
+pub mod tools;
+
+/// Draws frames onto the page's canvas, through an offscreen canvas holding
+/// the world at one pixel per cell, scaled and moved by the camera, and the
+/// brush over it.
+struct CanvasRenderer {
     canvas: web_sys::HtmlCanvasElement,
     context: web_sys::CanvasRenderingContext2d,
+    buffer: web_sys::HtmlCanvasElement,
+    buffer_context: web_sys::CanvasRenderingContext2d,
+    image_data: js_sys::Function,
+    camera: Rc<Cell<Camera>>,
+    brush: Option<BrushPreview>,
+}
+
+fn context_2d(canvas: &web_sys::HtmlCanvasElement) -> web_sys::CanvasRenderingContext2d {
+    canvas
+        .get_context("2d")
+        .unwrap()
+        .unwrap()
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .unwrap()
 }
 
-impl Renderer {
-    fn new() -> Self {
+impl CanvasRenderer {
+    fn new(width: i32, height: i32, camera: Rc<Cell<Camera>>) -> Self {
         let canvas = document().get_element_by_id("canvas").unwrap();
         let canvas: web_sys::HtmlCanvasElement = canvas
             .dyn_into::<web_sys::HtmlCanvasElement>()
             .map_err(|_| ())
             .unwrap();
-
-        let context = canvas
-            .get_context("2d")
+        let context = context_2d(&canvas);
+
+        let buffer = document().create_element("canvas")
+                               .unwrap()
+                               .dyn_into::<web_sys::HtmlCanvasElement>()
+                               .unwrap();
+        buffer.set_width(width as u32);
+        buffer.set_height(height as u32);
+        let buffer_context = context_2d(&buffer);
+        let image_data = js_sys::Reflect::get(&js_sys::global(), &"ImageData".into())
             .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
-
-        // context.set_image_smoothing_enabled(false);
+            .unchecked_into();
 
         Self {
             canvas,
             context,
+            buffer,
+            buffer_context,
+            image_data,
+            camera,
+            brush: None,
         }
     }
+}
+
+impl Renderer for CanvasRenderer {
+    fn render(&mut self, sandbox: &Sandbox) {
+        let frame = sandbox.frame();
+        let (width, height) = (frame.width() as u32, frame.height() as u32);
+        if (self.buffer.width(), self.buffer.height()) != (width, height) {
+            self.buffer.set_width(width);
+            self.buffer.set_height(height);
+        }
 
-    fn draw_canvas(&self, canvas: &web_sys::HtmlCanvasElement) {
-        self.context.draw_image_with_html_canvas_element(
-            canvas, 0.0, 0.0).unwrap();
+        // The image is a view of the sandbox's buffer in wasm memory, so
+        // nothing is copied until it reaches the canvas. The view is only
+        // valid until the next allocation, so it is used right away.
+        let pixels = unsafe { js_sys::Uint8ClampedArray::view(frame.data()) };
+        let arguments = js_sys::Array::of3(&pixels, &width.into(), &height.into());
+        let data: web_sys::ImageData = js_sys::Reflect::construct(&self.image_data, &arguments)
+            .unwrap()
+            .unchecked_into();
+
+        self.buffer_context.put_image_data(&data, 0.0, 0.0).unwrap();
+
+        let camera = self.camera.get();
+        let (canvas_width, canvas_height) = (self.canvas.width() as f64, self.canvas.height() as f64);
+        self.context.clear_rect(0.0, 0.0, canvas_width, canvas_height);
+        // Resizing the canvas resets this, so set it every frame.
+        self.context.set_image_smoothing_enabled(false);
+        self.context.draw_image_with_html_canvas_element_and_dw_and_dh(
+            &self.buffer,
+            camera.x as f64,
+            camera.y as f64,
+            (width as i32 * camera.zoom) as f64,
+            (height as i32 * camera.zoom) as f64,
+        ).unwrap();
+
+        if let Some(brush) = &self.brush {
+            let Color { r, g, b } = brush.color;
+            let style = format!("rgba({}, {}, {}, 0.8)", r, g, b);
+            self.context.set_fill_style(&JsValue::from(style));
+            let zoom = camera.zoom as f64;
+            for (x, y, length) in brush.outline() {
+                let (x, y) = camera.to_screen(x, y);
+                self.context.fill_rect(x as f64, y as f64, length as f64 * zoom, zoom);
+            }
+        }
     }
 }
 
 #[wasm_bindgen]
 pub struct IntervalHandle {
     interval_id: i32,
+    sandbox: Rc<RefCell<Sandbox>>,
+    gui_state: Rc<Cell<GuiState>>,
+    camera: Rc<Cell<Camera>>,
+    pointers: Rc<RefCell<Pointers>>,
     _closure: Closure<dyn FnMut()>,
 }
 
+#[wasm_bindgen]
+impl IntervalHandle {
+    /// Statistics for the last tick, see `TickStats::to_js`.
+    pub fn stats(&self) -> JsValue {
+        self.sandbox.borrow().stats().to_js()
+    }
+
+    /// The address of the world's RGBA buffer in wasm memory, see
+    /// `Sandbox::frame`. It moves when the world is resized, so ask again
+    /// every frame.
+    pub fn frame_ptr(&self) -> *const u8 {
+        self.sandbox.borrow().frame().data().as_ptr()
+    }
+
+    /// The length in bytes of the RGBA buffer.
+    pub fn frame_len(&self) -> usize {
+        self.sandbox.borrow().frame().data().len()
+    }
+
+    pub fn width(&self) -> i32 {
+        self.sandbox.borrow().width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.sandbox.borrow().height()
+    }
+
+    /// The wasm memory the buffer lives in.
+    pub fn memory(&self) -> JsValue {
+        wasm_bindgen::memory()
+    }
+
+    /// Names of the view modes, see `Overlay`.
+    pub fn overlays(&self) -> js_sys::Array {
+        Overlay::all().iter().map(|overlay| JsValue::from(overlay.name())).collect()
+    }
+
+    /// Switches to the named view mode. Returns false for unknown names.
+    pub fn set_overlay(&self, name: &str) -> bool {
+        match Overlay::from_name(name) {
+            Some(overlay) => {
+                self.sandbox.borrow_mut().set_overlay(overlay);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_lighting(&self, lighting: bool) {
+        self.sandbox.borrow_mut().set_lighting(lighting);
+    }
+
+    /// `[{ label, color }]` for the current view mode, with CSS colors.
+    pub fn legend(&self) -> js_sys::Array {
+        self.sandbox.borrow().frame().overlay().legend().into_iter()
+            .map(|(label, [r, g, b])| {
+                let entry = js_sys::Object::new();
+                js_sys::Reflect::set(&entry, &"label".into(), &JsValue::from(label)).unwrap();
+                let color = format!("rgb({}, {}, {})", r, g, b);
+                js_sys::Reflect::set(&entry, &"color".into(), &JsValue::from(color)).unwrap();
+                JsValue::from(entry)
+            })
+            .collect()
+    }
+
+    /// `[{ name, color }]` for every material that can be painted, with CSS
+    /// colors.
+    pub fn materials(&self) -> js_sys::Array {
+        Kind::materials()
+            .map(|kind| {
+                let Color { r, g, b } = kind.color();
+                let entry = js_sys::Object::new();
+                js_sys::Reflect::set(&entry, &"name".into(), &kind.name().into()).unwrap();
+                let color = format!("rgb({}, {}, {})", r, g, b);
+                js_sys::Reflect::set(&entry, &"color".into(), &JsValue::from(color)).unwrap();
+                JsValue::from(entry)
+            })
+            .collect()
+    }
+
+    /// Names of the tools, besides the plain brush.
+    pub fn tools(&self) -> js_sys::Array {
+        tools::TOOLS.iter().map(|(name, _)| JsValue::from(*name)).collect()
+    }
+
+    /// The brush's material, tool or size, see `set_material` and so on.
+    pub fn brush(&self) -> JsValue {
+        let gui_state = self.gui_state.get();
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"material".into(), &gui_state.kind.name().into()).unwrap();
+        let tool = gui_state.tool.map_or(JsValue::NULL, JsValue::from);
+        js_sys::Reflect::set(&object, &"tool".into(), &tool).unwrap();
+        js_sys::Reflect::set(&object, &"size".into(), &JsValue::from(gui_state.size)).unwrap();
+        object.into()
+    }
+
+    /// Paints with the named material. Returns false for unknown names.
+    pub fn set_material(&self, name: &str) -> bool {
+        match Kind::materials().find(|kind| kind.name() == name) {
+            Some(kind) => {
+                let mut gui_state = self.gui_state.get();
+                gui_state.kind = kind;
+                self.gui_state.set(gui_state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Paints with the named tool, or the plain brush for `null`. Returns
+    /// false for unknown names.
+    pub fn set_tool(&self, name: Option<String>) -> bool {
+        let tool = match name {
+            Some(name) => match tools::TOOLS.iter().find(|(tool, _)| *tool == name) {
+                Some((tool, _)) => Some(*tool),
+                None => return false,
+            },
+            None => None,
+        };
+        let mut gui_state = self.gui_state.get();
+        gui_state.tool = tool;
+        self.gui_state.set(gui_state);
+        true
+    }
+
+    /// Sets the brush's radius, which is kept between 1 and the world's width.
+    pub fn set_brush_size(&self, size: u32) {
+        let width = self.sandbox.borrow().width() as u32;
+        let mut gui_state = self.gui_state.get();
+        gui_state.size = size.max(1).min(width.max(1));
+        self.gui_state.set(gui_state);
+    }
+
+    /// What the cell at `(x, y)` holds, see `Inspection::to_js`, or `null`
+    /// outside the world.
+    pub fn inspect(&self, x: i32, y: i32) -> JsValue {
+        self.sandbox.borrow().inspect(x, y).map_or(JsValue::NULL, |inspection| inspection.to_js())
+    }
+
+    /// `[x, y]` of the cell under the pointer, which may be outside the world.
+    pub fn hovered(&self) -> js_sys::Array {
+        let (x, y) = self.pointers.borrow().hovered();
+        let (x, y) = self.camera.get().to_world(x, y);
+        js_sys::Array::of2(&x.into(), &y.into())
+    }
+
+    /// Script errors from the last tick.
+    pub fn errors(&self) -> js_sys::Array {
+        self.sandbox.borrow().errors().iter().map(|error| JsValue::from(error.as_str())).collect()
+    }
+
+    /// Names of the built-in generators.
+    pub fn generators(&self) -> js_sys::Array {
+        generators::GENERATORS.iter().map(|(name, _)| JsValue::from(*name)).collect()
+    }
+
+    /// Starts over with a world from the named built-in generator.
+    pub fn generate(&self, name: &str, seed: u32) -> Result<(), JsValue> {
+        let script = generators::builtin(name)
+            .ok_or_else(|| JsValue::from(format!("unknown generator `{}`", name)))?;
+        self.sandbox.borrow_mut().generate(script, seed as u64)
+            .map_err(|error| JsValue::from(error.to_string()))
+    }
+
+    pub fn set_debugging(&self, debugging: bool) {
+        self.sandbox.borrow_mut().set_debugging(debugging);
+    }
+
+    pub fn break_at(&self, x: i32, y: i32) {
+        self.sandbox.borrow_mut().debugger_mut().add_breakpoint(Breakpoint::Cell(x, y));
+    }
+
+    /// Breaks before any cell of the named material updates. Returns false
+    /// for unknown names.
+    pub fn break_on(&self, material: &str) -> bool {
+        let kind = (0..Kind::COUNT as i32)
+            .filter_map(Kind::from_value)
+            .find(|kind| kind.name() == material);
+        match kind {
+            Some(kind) => {
+                self.sandbox.borrow_mut().debugger_mut().add_breakpoint(Breakpoint::Material(kind));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_breakpoints(&self) {
+        self.sandbox.borrow_mut().debugger_mut().clear_breakpoints();
+    }
+
+    pub fn step(&self) {
+        self.sandbox.borrow_mut().step();
+    }
+
+    pub fn resume(&self) {
+        self.sandbox.borrow_mut().resume();
+    }
+
+    /// Where the debugger is paused, see `Pause::to_js`, or `null`.
+    pub fn paused(&self) -> JsValue {
+        self.sandbox.borrow().debugger().paused().map_or(JsValue::NULL, |pause| pause.to_js())
+    }
+
+    /// This tick's writes so far, see `SetCall::to_js`.
+    pub fn trace(&self) -> JsValue {
+        self.sandbox.borrow().trace().iter().map(|call| call.to_js()).collect::<js_sys::Array>().into()
+    }
+}
+
 impl Drop for IntervalHandle {
     fn drop(&mut self) {
         let window = web_sys::window().unwrap();
@@ -75,73 +376,53 @@ pub fn run() -> Result<IntervalHandle, JsValue> {
     #[cfg(debug_assertions)]
         console_error_panic_hook::set_once();
 
-    let renderer = Renderer::new();
-    let canvas = renderer.canvas.clone();
-
     let width: usize = 400;
     let height: usize = 400;
 
+    let camera = Rc::new(Cell::new(Camera::new()));
+    let mut renderer = CanvasRenderer::new(width as i32, height as i32, camera.clone());
+    let canvas = renderer.canvas.clone();
     canvas.set_width(width as u32);
     canvas.set_height(width as u32);
 
-    let mut sandbox = Sandbox::new(width as i32, height as i32);
-    let world = sandbox.world();
+    let sandbox = Rc::new(RefCell::new(Sandbox::new(width as i32, height as i32)));
 
     let gui_state = Rc::new(Cell::new(GuiState::new()));
+    let pointers = Rc::new(RefCell::new(Pointers::new()));
 
     let gui_state_tick = gui_state.clone();
+    let camera_tick = camera.clone();
+    let pointers_tick = pointers.clone();
+    let sandbox_tick = sandbox.clone();
     let tick = Closure::wrap(Box::new(move || {
         let gui_state = gui_state_tick.get();
-        let user_event = if gui_state.down &&
-            gui_state.x >= 0 && gui_state.x < width as i32 &&
-            gui_state.y >= 0 && gui_state.y < height as i32 {
-            Some(UserEvent {
-                x: gui_state.x,
-                y: gui_state.y,
-                kind: gui_state.kind,
-                size: gui_state.size,
-            })
-        } else {
-            None
+        let brush = UserEvent {
+            tool: gui_state.tool.map(String::from),
+            ..UserEvent::new(0, 0, gui_state.kind, gui_state.size)
         };
+        let user_events = pointers_tick.borrow_mut()
+            .events(&camera_tick.get(), width as i32, height as i32, &brush);
 
-        sandbox.tick(user_event);
+        sandbox_tick.borrow_mut().tick_with(user_events);
     }) as Box<dyn FnMut()>);
 
     let render = Rc::new(RefCell::new(None));
     let render_clone = render.clone();
 
-    let canvas_buffer = document().create_element("canvas")
-                                  .unwrap()
-                                  .dyn_into::<web_sys::HtmlCanvasElement>()
-                                  .unwrap();
-    canvas_buffer.set_width(width as u32);
-    canvas_buffer.set_height(height as u32);
-    let context_buffer = canvas_buffer
-        .get_context("2d")
-        .unwrap()
-        .unwrap()
-        .dyn_into::<web_sys::CanvasRenderingContext2d>()
-        .unwrap();
-
+    let sandbox_render = sandbox.clone();
+    let gui_state_render = gui_state.clone();
+    let pointers_render = pointers.clone();
     *render_clone.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        let mut image_index = 0;
-        let mut data: Vec<u8> = vec![255; width * height * 4];
-        for index in 0..(width * height) {
-            let particle = unsafe { &*world.add(index) };
-            let color = particle.extra.color;
-
-            data[image_index] = color.r;
-            data[image_index + 1] = color.g;
-            data[image_index + 2] = color.b;
-            image_index += 4;
-        }
-
-        let data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
-            Clamped(&mut data), width as u32, height as u32).unwrap();
-
-        context_buffer.put_image_data(&data, 0.0, 0.0).unwrap();
-        renderer.draw_canvas(&canvas_buffer);
+        let pointers = pointers_render.borrow();
+        renderer.brush = if pointers.hovering() {
+            let gui_state = gui_state_render.get();
+            let (x, y) = pointers.hovered();
+            let (x, y) = renderer.camera.get().to_world(x, y);
+            Some(BrushPreview::new(x, y, gui_state.kind, gui_state.size, gui_state.tool))
+        } else {
+            None
+        };
+        renderer.render(&sandbox_render.borrow());
 
         request_animation_frame(render.borrow().as_ref().unwrap());
     }) as Box<dyn FnMut()>));
@@ -153,65 +434,84 @@ pub fn run() -> Result<IntervalHandle, JsValue> {
             tick.as_ref().unchecked_ref(), 0)?;
 
     {
-        let gui_state = gui_state.clone();
-        let closure = Closure::wrap(Box::new(move |_: web_sys::MouseEvent| {
-            let mut gui_state_inner = gui_state.get();
-            gui_state_inner.down = true;
-            gui_state.set(gui_state_inner);
+        let pointers = pointers.clone();
+        let camera = camera.clone();
+        let canvas_capture = canvas.clone();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
+            // Keeps the pointer's events coming while it is dragged off the canvas.
+            canvas_capture.set_pointer_capture(event.pointer_id()).ok();
+            pointers.borrow_mut().down(pointer_input(&event), event.button(), &camera.get());
         }) as Box<dyn FnMut(_)>);
 
-        canvas.add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("pointerdown", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
 
     {
-        let gui_state = gui_state.clone();
-        let closure = Closure::wrap(Box::new(move |_: web_sys::MouseEvent| {
-            let mut gui_state_inner = gui_state.get();
-            gui_state_inner.down = false;
-            gui_state.set(gui_state_inner);
+        let pointers = pointers.clone();
+        let camera = camera.clone();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
+            let mut camera_inner = camera.get();
+            pointers.borrow_mut().moved(pointer_input(&event), &mut camera_inner);
+            camera.set(camera_inner);
         }) as Box<dyn FnMut(_)>);
 
-        canvas.add_event_listener_with_callback("mouseup", closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("pointermove", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
 
     {
-        let gui_state = gui_state.clone();
-        let closure = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
-            let mut gui_state_inner = gui_state.get();
-            gui_state_inner.x = event.offset_x();
-            gui_state_inner.y = event.offset_y();
-            gui_state.set(gui_state_inner);
+        let pointers = pointers.clone();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
+            pointers.borrow_mut().up(event.pointer_id());
         }) as Box<dyn FnMut(_)>);
 
-        canvas.add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref())?;
+        for name in &["pointerup", "pointercancel"] {
+            canvas.add_event_listener_with_callback(name, closure.as_ref().unchecked_ref())?;
+        }
         closure.forget();
     }
 
     {
-        let gui_state = gui_state.clone();
-        let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
-            let mut gui_state_inner = gui_state.get();
-            match event.key().as_str() {
-                "1" => gui_state_inner.kind = Kind::Sand,
-                "2" => gui_state_inner.kind = Kind::Plant,
-                "3" => gui_state_inner.kind = Kind::Fire,
-                "4" => gui_state_inner.kind = Kind::Water,
-                "e" => gui_state_inner.kind = Kind::Empty,
-                "+" => if gui_state_inner.size < width as u32 { gui_state_inner.size += 1 },
-                "-" => if gui_state_inner.size > 1 { gui_state_inner.size -= 1 },
-                _ => {}
-            }
-            gui_state.set(gui_state_inner);
+        let pointers = pointers.clone();
+        let closure = Closure::wrap(Box::new(move |_: web_sys::PointerEvent| {
+            pointers.borrow_mut().left();
         }) as Box<dyn FnMut(_)>);
 
-        document().add_event_listener_with_callback("keypress", closure.as_ref().unchecked_ref())?;
+        canvas.add_event_listener_with_callback("pointerleave", closure.as_ref().unchecked_ref())?;
+        closure.forget();
+    }
+
+    {
+        let camera = camera.clone();
+        let closure = Closure::wrap(Box::new(move |event: web_sys::WheelEvent| {
+            event.prevent_default();
+            let mut camera_inner = camera.get();
+            let steps = if event.delta_y() < 0.0 { 1 } else { -1 };
+            camera_inner.zoom_at(event.offset_x(), event.offset_y(), steps);
+            camera.set(camera_inner);
+        }) as Box<dyn FnMut(_)>);
+
+        canvas.add_event_listener_with_callback("wheel", closure.as_ref().unchecked_ref())?;
+        closure.forget();
+    }
+
+    {
+        // Right-dragging pans, so keep the menu out of the way.
+        let closure = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
+            event.prevent_default();
+        }) as Box<dyn FnMut(_)>);
+
+        canvas.add_event_listener_with_callback("contextmenu", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
 
     Ok(IntervalHandle {
         interval_id,
+        sandbox,
+        gui_state,
+        camera,
+        pointers,
         _closure: tick,
     })
 }
@@ -219,20 +519,35 @@ pub fn run() -> Result<IntervalHandle, JsValue> {
 #[derive(Copy, Clone)]
 pub(crate) struct GuiState {
     pub(crate) kind: Kind,
-    pub(crate) x: i32,
-    pub(crate) y: i32,
-    pub(crate) down: bool,
     pub(crate) size: u32,
+    pub(crate) tool: Option<&'static str>,
 }
 
 impl GuiState {
     pub fn new() -> Self {
         Self {
             kind: Kind::Sand,
-            x: 0,
-            y: 0,
             size: 25,
-            down: false,
+            tool: None,
         }
     }
 }
+
+fn pointer_input(event: &web_sys::PointerEvent) -> PointerInput {
+    PointerInput {
+        id: event.pointer_id(),
+        kind: PointerKind::from_name(&event.pointer_type()),
+        x: event.offset_x(),
+        y: event.offset_y(),
+        pressure: event.pressure(),
+        modifiers: modifiers(event),
+    }
+}
+
+fn modifiers(event: &web_sys::MouseEvent) -> Modifiers {
+    Modifiers {
+        shift: event.shift_key(),
+        ctrl: event.ctrl_key(),
+        alt: event.alt_key(),
+    }
+}
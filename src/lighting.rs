@@ -0,0 +1,169 @@
+//! A lighting pass over the frame: fire lights up the cells around it,
+//! solids darken with depth below their surface, and liquids let the floor
+//! beneath them show through.
+
+use crate::engine::{Kind, Particle};
+
+/// How much light is left after each cell it travels.
+const FALLOFF: f32 = 0.85;
+/// Light this weak is left out.
+const DARK: f32 = 0.02;
+const GLOW: [f32; 3] = [255.0, 140.0, 50.0];
+const GLOW_STRENGTH: f32 = 0.6;
+
+/// Cells below a surface that get darker, and how dark the last one is.
+const SHADE_DEPTH: u32 = 12;
+const SHADE: f32 = 0.4;
+const HIGHLIGHT: f32 = 1.12;
+
+/// Opacity of a liquid cell right above its floor, and how much each cell
+/// of liquid above that adds.
+const LIQUID_OPACITY: f32 = 0.55;
+const LIQUID_OPACITY_PER_CELL: f32 = 0.05;
+
+enum Surface {
+    Emissive,
+    Solid,
+    Liquid,
+    Open,
+}
+
+fn surface(kind: Kind) -> Surface {
+    match kind {
+        Kind::Fire => Surface::Emissive,
+        Kind::Sand | Kind::Plant => Surface::Solid,
+        Kind::Water => Surface::Liquid,
+        Kind::Empty | Kind::OutOfBounds => Surface::Open,
+    }
+}
+
+/// How much a solid is brightened or darkened at each depth, in 256ths.
+fn shading() -> [u32; SHADE_DEPTH as usize + 2] {
+    let mut shading = [0; SHADE_DEPTH as usize + 2];
+    for (depth, factor) in shading.iter_mut().enumerate() {
+        let scale = match depth {
+            0 => 1.0,
+            1 => HIGHLIGHT,
+            _ => 1.0 - SHADE * (depth - 1) as f32 / SHADE_DEPTH as f32,
+        };
+        *factor = (scale * 256.0) as u32;
+    }
+    shading
+}
+
+/// Keeps its per-cell and per-column state between frames, so passes don't
+/// allocate.
+#[derive(Default)]
+pub struct Lighting {
+    light: Vec<f32>,
+    /// Solid cells in each column down to the current row.
+    depth: Vec<u32>,
+    /// The color each column's liquid lies on, and how many liquid cells
+    /// above it the current row is.
+    floor: Vec<Option<([u8; 3], u32)>>,
+}
+
+impl Lighting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lights `data`, the RGBA colors of `particles`.
+    pub(crate) fn apply(&mut self, data: &mut [u8], particles: &[Particle], width: i32, height: i32) {
+        let (width, height) = (width as usize, height as usize);
+        self.shade(data, particles, width, height);
+        self.glow(data, particles, width, height);
+    }
+
+    /// Shades solids from the top down, then tints liquids from the bottom
+    /// up, once the floor they lie on is known. Rows are walked in order,
+    /// keeping what each column needs.
+    fn shade(&mut self, data: &mut [u8], particles: &[Particle], width: usize, height: usize) {
+        let shading = shading();
+        self.depth.clear();
+        self.depth.resize(width, 0);
+        for y in 0..height {
+            let cells = data[y * width * 4..(y + 1) * width * 4].chunks_exact_mut(4);
+            let row = &particles[y * width..(y + 1) * width];
+            for ((pixel, particle), depth) in cells.zip(row).zip(&mut self.depth) {
+                match surface(particle.kind) {
+                    Surface::Solid => {
+                        *depth = (*depth + 1).min(SHADE_DEPTH + 1);
+                        let factor = shading[*depth as usize];
+                        for channel in &mut pixel[..3] {
+                            *channel = ((*channel as u32 * factor) >> 8).min(255) as u8;
+                        }
+                    }
+                    _ => *depth = 0,
+                }
+            }
+        }
+
+        self.floor.clear();
+        self.floor.resize(width, None);
+        for y in (0..height).rev() {
+            let cells = data[y * width * 4..(y + 1) * width * 4].chunks_exact_mut(4);
+            let row = &particles[y * width..(y + 1) * width];
+            for ((pixel, particle), floor) in cells.zip(row).zip(&mut self.floor) {
+                match surface(particle.kind) {
+                    Surface::Liquid => {
+                        if let Some((color, above)) = floor {
+                            let opacity = (LIQUID_OPACITY + LIQUID_OPACITY_PER_CELL * *above as f32).min(1.0);
+                            for (channel, color) in pixel[..3].iter_mut().zip(color.iter()) {
+                                *channel = (*color as f32 * (1.0 - opacity) + *channel as f32 * opacity) as u8;
+                            }
+                            *above += 1;
+                        }
+                    }
+                    Surface::Solid | Surface::Emissive => *floor = Some(([pixel[0], pixel[1], pixel[2]], 0)),
+                    Surface::Open => *floor = None,
+                }
+            }
+        }
+    }
+
+    fn glow(&mut self, data: &mut [u8], particles: &[Particle], width: usize, height: usize) {
+        self.light.clear();
+        self.light.extend(particles.iter().map(|particle| match surface(particle.kind) {
+            Surface::Emissive => particle.extra.energy(),
+            _ => 0.0,
+        }));
+
+        // Spreading the light along rows and then along columns makes it fall
+        // off with the distance in cells. Only rows with something emissive
+        // have light to spread along them.
+        let light = &mut self.light;
+        for row in light.chunks_exact_mut(width.max(1)) {
+            if row.iter().all(|&light| light == 0.0) {
+                continue;
+            }
+            for x in 1..width {
+                row[x] = row[x].max(row[x - 1] * FALLOFF);
+            }
+            for x in (1..width).rev() {
+                row[x - 1] = row[x - 1].max(row[x] * FALLOFF);
+            }
+        }
+        for y in 1..height {
+            let (above, row) = light[(y - 1) * width..(y + 1) * width].split_at_mut(width);
+            for (light, above) in row.iter_mut().zip(above.iter()) {
+                *light = light.max(above * FALLOFF);
+            }
+        }
+        for y in (1..height).rev() {
+            let (row, below) = light[(y - 1) * width..(y + 1) * width].split_at_mut(width);
+            for (light, below) in row.iter_mut().zip(below.iter()) {
+                *light = light.max(below * FALLOFF);
+            }
+        }
+
+        for ((pixel, particle), &light) in data.chunks_exact_mut(4).zip(particles).zip(light.iter()) {
+            if light < DARK || particle.kind == Kind::Fire {
+                continue;
+            }
+            for (channel, glow) in pixel[..3].iter_mut().zip(&GLOW) {
+                *channel = (*channel as f32 + glow * light * GLOW_STRENGTH).min(255.0) as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+const D: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+const GRADIENTS: [(f64, f64); 8] = [
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+    (D, D), (-D, D), (D, -D), (-D, -D),
+];
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Seeded 2D noise. Every function returns values in `[-1, 1]` that vary
+/// smoothly over roughly one unit, so scale coordinates down for larger
+/// features.
+#[derive(Clone)]
+pub struct Noise {
+    permutation: [u8; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut permutation = [0; 512];
+        for (index, value) in permutation.iter_mut().enumerate() {
+            *value = values[index % 256];
+        }
+        Self { permutation }
+    }
+
+    /// Only the low byte of each coordinate is used, so neighbors of
+    /// `i32::MAX` wrap around to `i32::MIN` consistently.
+    fn hash(&self, x: i32, y: i32) -> usize {
+        let x = (x & 255) as usize;
+        let y = (y & 255) as usize;
+        self.permutation[self.permutation[x] as usize + y] as usize
+    }
+
+    fn gradient(&self, x: i32, y: i32, d_x: f64, d_y: f64) -> f64 {
+        let (g_x, g_y) = GRADIENTS[self.hash(x, y) % GRADIENTS.len()];
+        g_x * d_x + g_y * d_y
+    }
+
+    /// Random values at integer points, smoothly interpolated between them.
+    pub fn value(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (t_x, t_y) = (fade(x - x0), fade(y - y0));
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let (x1, y1) = (x0.wrapping_add(1), y0.wrapping_add(1));
+        let corner = |x, y| self.hash(x, y) as f64 / 127.5 - 1.0;
+        lerp(
+            lerp(corner(x0, y0), corner(x1, y0), t_x),
+            lerp(corner(x0, y1), corner(x1, y1), t_x),
+            t_y,
+        )
+    }
+
+    pub fn perlin(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (d_x, d_y) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let (x1, y1) = (x0.wrapping_add(1), y0.wrapping_add(1));
+        let value = lerp(
+            lerp(self.gradient(x0, y0, d_x, d_y), self.gradient(x1, y0, d_x - 1.0, d_y), fade(d_x)),
+            lerp(self.gradient(x0, y1, d_x, d_y - 1.0), self.gradient(x1, y1, d_x - 1.0, d_y - 1.0), fade(d_x)),
+            fade(d_y),
+        );
+        // Unit gradients keep 2D Perlin noise within ±√½.
+        (value * std::f64::consts::SQRT_2).max(-1.0).min(1.0)
+    }
+
+    pub fn simplex(&self, x: f64, y: f64) -> f64 {
+        let skew = 0.5 * (3.0f64.sqrt() - 1.0);
+        let unskew = (3.0 - 3.0f64.sqrt()) / 6.0;
+
+        let s = (x + y) * skew;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * unskew;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let (i, j) = (i as i32, j as i32);
+
+        let corners = [
+            (i, j, x0, y0),
+            (i.wrapping_add(i1), j.wrapping_add(j1), x0 - i1 as f64 + unskew, y0 - j1 as f64 + unskew),
+            (i.wrapping_add(1), j.wrapping_add(1), x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew),
+        ];
+        let mut value = 0.0;
+        for &(corner_x, corner_y, d_x, d_y) in &corners {
+            let falloff = 0.5 - d_x * d_x - d_y * d_y;
+            if falloff > 0.0 {
+                value += falloff.powi(4) * self.gradient(corner_x, corner_y, d_x, d_y);
+            }
+        }
+        (value * 99.0).max(-1.0).min(1.0)
+    }
+}
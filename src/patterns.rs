@@ -0,0 +1,184 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::engine::{Behavior, Kind, Particle, WorldView, EMPTY};
+
+/// One cell of a pattern: a material, or `*` to match anything and leave it
+/// unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cell {
+    Any,
+    Is(Kind),
+}
+
+impl Cell {
+    fn from_char(c: char) -> Result<Cell, String> {
+        if c == '*' {
+            return Ok(Cell::Any);
+        }
+        Kind::from_char(c)
+            .map(Cell::Is)
+            .ok_or_else(|| format!("unknown pattern cell `{}`", c))
+    }
+}
+
+/// A 3×3 rewrite rule centered on the updated cell.
+///
+/// Cells use the same characters as `Kind::to_char`, plus `*` for "anything".
+/// A replacement cell that names a material found elsewhere in the match
+/// moves that particle there, so `S` over `.` replaced by `.` over `S` makes
+/// the sand fall rather than recreating it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub matches: [[Cell; 3]; 3],
+    pub replaces: [[Cell; 3]; 3],
+    pub probability: f64,
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+}
+
+fn parse_grid(rows: &[&str]) -> Result<[[Cell; 3]; 3], String> {
+    if rows.len() != 3 {
+        return Err(format!("patterns must have 3 rows, found {}", rows.len()));
+    }
+    let mut grid = [[Cell::Any; 3]; 3];
+    for (y, row) in rows.iter().enumerate() {
+        let cells: Vec<char> = row.chars().collect();
+        if cells.len() != 3 {
+            return Err(format!("pattern rows must have 3 cells, found `{}`", row));
+        }
+        for (x, c) in cells.into_iter().enumerate() {
+            grid[y][x] = Cell::from_char(c)?;
+        }
+    }
+    Ok(grid)
+}
+
+impl Pattern {
+    pub fn new(matches: &[&str], replaces: &[&str]) -> Result<Pattern, String> {
+        Ok(Pattern {
+            matches: parse_grid(matches)?,
+            replaces: parse_grid(replaces)?,
+            probability: 1.0,
+            mirror_x: false,
+            mirror_y: false,
+        })
+    }
+
+    fn mirrored(&self, x: bool, y: bool) -> Pattern {
+        let flip = |grid: &[[Cell; 3]; 3]| {
+            let mut flipped = *grid;
+            for (row, cells) in flipped.iter_mut().enumerate() {
+                for (column, cell) in cells.iter_mut().enumerate() {
+                    let from_row = if y { 2 - row } else { row };
+                    let from_column = if x { 2 - column } else { column };
+                    *cell = grid[from_row][from_column];
+                }
+            }
+            flipped
+        };
+        Pattern {
+            matches: flip(&self.matches),
+            replaces: flip(&self.replaces),
+            mirror_x: false,
+            mirror_y: false,
+            ..*self
+        }
+    }
+
+    /// This pattern and all of its mirror images.
+    fn variants(&self) -> Vec<Pattern> {
+        let mut variants = vec![self.mirrored(false, false)];
+        if self.mirror_x {
+            variants.push(self.mirrored(true, false));
+        }
+        if self.mirror_y {
+            variants.push(self.mirrored(false, true));
+        }
+        if self.mirror_x && self.mirror_y {
+            variants.push(self.mirrored(true, true));
+        }
+        variants
+    }
+
+    fn matches(&self, neighborhood: &[[Particle; 3]; 3]) -> bool {
+        self.matches.iter().flatten().zip(neighborhood.iter().flatten()).all(|(cell, particle)| match cell {
+            Cell::Is(kind) => particle.kind == *kind,
+            Cell::Any => true,
+        })
+    }
+
+    /// Where the particle for a replacement cell comes from: the center first,
+    /// then anywhere else in the match.
+    fn source(&self, kind: Kind) -> Option<(usize, usize)> {
+        if self.matches[1][1] == Cell::Is(kind) {
+            return Some((1, 1));
+        }
+        (0..9).map(|i| (i % 3, i / 3))
+            .find(|&(x, y)| self.matches[y][x] == Cell::Is(kind))
+    }
+
+    fn apply(&self, view: &mut WorldView, neighborhood: &[[Particle; 3]; 3]) {
+        for y in 0..3 {
+            for x in 0..3 {
+                let kind = match self.replaces[y][x] {
+                    Cell::Any => continue,
+                    Cell::Is(kind) => kind,
+                };
+                if self.matches[y][x] == Cell::Is(kind) || kind == Kind::OutOfBounds {
+                    continue;
+                }
+                let particle = if kind == Kind::Empty {
+                    EMPTY
+                } else {
+                    match self.source(kind) {
+                        Some((from_x, from_y)) => neighborhood[from_y][from_x],
+                        None => Particle::new(kind),
+                    }
+                };
+                view.set(x as i32 - 1, y as i32 - 1, particle);
+            }
+        }
+    }
+}
+
+/// A material defined by a list of patterns. The first one that matches, and
+/// passes its probability check, is applied.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PatternRules {
+    patterns: Vec<Pattern>,
+    variants: Vec<Vec<Pattern>>,
+}
+
+impl PatternRules {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let variants = patterns.iter().map(Pattern::variants).collect();
+        Self { patterns, variants }
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+}
+
+impl<R: Rng> Behavior<R> for PatternRules {
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        let mut neighborhood = [[EMPTY; 3]; 3];
+        for (y, row) in neighborhood.iter_mut().enumerate() {
+            for (x, particle) in row.iter_mut().enumerate() {
+                *particle = view.get(x as i32 - 1, y as i32 - 1);
+            }
+        }
+
+        let mut order = [0, 1, 2, 3];
+        for variants in &self.variants {
+            let order = &mut order[..variants.len()];
+            order.shuffle(rng);
+            for variant in order.iter().map(|&i| &variants[i]) {
+                if variant.matches(&neighborhood) && rng.gen_bool(variant.probability) {
+                    variant.apply(view, &neighborhood);
+                    return;
+                }
+            }
+        }
+    }
+}
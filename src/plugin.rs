@@ -0,0 +1,401 @@
+//! Materials implemented as third-party WebAssembly modules.
+//!
+//! A plugin exports one function, called for every cell of the material it
+//! is registered for:
+//!
+//! - `update(x: i32, y: i32)`, with the absolute coordinates of the cell.
+//!
+//! It may import any of these functions from the `env` module, and nothing
+//! else:
+//!
+//! - `get(x: i32, y: i32) -> i32` returns the kind of the particle at (x, y).
+//! - `set(x: i32, y: i32, kind: i32)` replaces the particle at (x, y) with a
+//!   new particle of `kind`.
+//! - `swap(x0: i32, y0: i32, x1: i32, y1: i32)` swaps two particles, keeping
+//!   their colors and energy.
+//! - `rand() -> f64` returns a random number in `[0, 1)`.
+//!
+//! Kinds are numbered 0 sand, 1 plant, 2 fire, 3 water, 4 empty and 5 out of
+//! bounds. Writes out of bounds or with unknown kinds are ignored.
+//!
+//! An update that runs more than `MAX_INSTRUCTIONS` instructions traps, and
+//! the cell is left as the plugin last wrote it.
+
+use std::fmt;
+use rand::Rng;
+use walrus::ir::{BinaryOp, Instr, InstrSeqId, Value};
+use walrus::{ExportItem, FunctionBuilder, GlobalId, ImportKind, InitExpr, LocalFunction, Module, ValType};
+use crate::engine::{Behavior, Kind, Particle, WorldView};
+
+/// Instructions one plugin update may run, counted as loops iterate and
+/// functions are called.
+pub const MAX_INSTRUCTIONS: i32 = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PluginError {
+    Invalid(String),
+    Instantiation(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginError::Invalid(message) => write!(f, "invalid plugin: {}", message),
+            PluginError::Instantiation(message) => write!(f, "could not instantiate plugin: {}", message),
+        }
+    }
+}
+
+const IMPORTS: [(&str, &[ValType], &[ValType]); 4] = [
+    ("get", &[ValType::I32, ValType::I32], &[ValType::I32]),
+    ("set", &[ValType::I32, ValType::I32, ValType::I32], &[]),
+    ("swap", &[ValType::I32, ValType::I32, ValType::I32, ValType::I32], &[]),
+    ("rand", &[], &[ValType::F64]),
+];
+
+/// Checks that `bytes` is a wasm module following the plugin ABI.
+pub fn validate(bytes: &[u8]) -> Result<(), PluginError> {
+    let module = Module::from_buffer(bytes)
+        .map_err(|error| PluginError::Invalid(error.to_string()))?;
+
+    for import in module.imports.iter() {
+        let function = match import.kind {
+            ImportKind::Function(function) => function,
+            _ => return Err(PluginError::Invalid(
+                format!("`{}.{}` must be a function", import.module, import.name))),
+        };
+        let ty = module.types.get(module.funcs.get(function).ty());
+        let expected = IMPORTS.iter()
+            .find(|(name, _, _)| import.module == "env" && import.name == *name);
+        match expected {
+            Some((_, params, results)) if ty.params() == *params && ty.results() == *results => {}
+            Some(_) => return Err(PluginError::Invalid(
+                format!("`env.{}` has the wrong signature", import.name))),
+            None => return Err(PluginError::Invalid(
+                format!("unknown import `{}.{}`", import.module, import.name))),
+        }
+    }
+
+    let update = module.exports.iter()
+        .find(|export| export.name == "update")
+        .ok_or_else(|| PluginError::Invalid("missing `update` export".to_string()))?;
+    match update.item {
+        ExportItem::Function(function) => {
+            let ty = module.types.get(module.funcs.get(function).ty());
+            if ty.params() != [ValType::I32, ValType::I32] || !ty.results().is_empty() {
+                return Err(PluginError::Invalid("`update` must take (i32, i32) and return nothing".to_string()));
+            }
+        }
+        _ => return Err(PluginError::Invalid("`update` must be a function".to_string())),
+    }
+
+    Ok(())
+}
+
+/// What running `seq` once costs, up to its end or a nested loop, which pays
+/// for itself. Both arms of an `if` are counted.
+fn cost(function: &LocalFunction, seq: InstrSeqId) -> i32 {
+    function.block(seq).instrs.iter().map(|(instr, _)| match instr {
+        Instr::Block(block) => 1 + cost(function, block.seq),
+        Instr::IfElse(if_else) => 1 + cost(function, if_else.consequent) + cost(function, if_else.alternative),
+        _ => 1,
+    }).sum()
+}
+
+/// The loops in `seq`, including loops nested in them.
+fn loops(function: &LocalFunction, seq: InstrSeqId, found: &mut Vec<InstrSeqId>) {
+    for (instr, _) in &function.block(seq).instrs {
+        match instr {
+            Instr::Block(block) => loops(function, block.seq, found),
+            Instr::Loop(loop_) => {
+                found.push(loop_.seq);
+                loops(function, loop_.seq, found);
+            }
+            Instr::IfElse(if_else) => {
+                loops(function, if_else.consequent, found);
+                loops(function, if_else.alternative, found);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rewrites a valid plugin so every function call and loop iteration pays
+/// for its instructions out of a budget, trapping once it runs out. The
+/// exported `update` refills it first. Neither wasmi nor browsers limit
+/// how long a module runs themselves. The budget starts full so a start
+/// function can run when the module is instantiated.
+fn meter(bytes: &[u8]) -> Result<Vec<u8>, PluginError> {
+    let mut module = Module::from_buffer(bytes)
+        .map_err(|error| PluginError::Invalid(error.to_string()))?;
+    let budget = module.globals.add_local(ValType::I32, true, InitExpr::Value(Value::I32(MAX_INSTRUCTIONS)));
+
+    for (_, function) in module.funcs.iter_local_mut() {
+        let mut seqs = vec![function.entry_block()];
+        loops(function, function.entry_block(), &mut seqs);
+        for seq in seqs {
+            let cost = cost(function, seq);
+            charge(function, seq, cost, budget);
+        }
+    }
+
+    let export = module.exports.iter()
+        .find(|export| export.name == "update")
+        .ok_or_else(|| PluginError::Invalid("missing `update` export".to_string()))?;
+    let (export, update) = match export.item {
+        ExportItem::Function(update) => (export.id(), update),
+        _ => return Err(PluginError::Invalid("`update` must be a function".to_string())),
+    };
+    let mut refill = FunctionBuilder::new(&mut module.types, &[ValType::I32, ValType::I32], &[]);
+    let x = module.locals.add(ValType::I32);
+    let y = module.locals.add(ValType::I32);
+    refill.func_body()
+        .i32_const(MAX_INSTRUCTIONS)
+        .global_set(budget)
+        .local_get(x)
+        .local_get(y)
+        .call(update);
+    let refill = refill.finish(vec![x, y], &mut module.funcs);
+    module.exports.get_mut(export).item = ExportItem::Function(refill);
+
+    Ok(module.emit_wasm())
+}
+
+/// Takes `cost` out of `budget` at the start of `seq`, trapping if it is
+/// overdrawn.
+fn charge(function: &mut LocalFunction, seq: InstrSeqId, cost: i32, budget: GlobalId) {
+    function.builder_mut().instr_seq(seq).block_at(0, None, |block| {
+        block.global_get(budget)
+            .i32_const(cost)
+            .binop(BinaryOp::I32Sub)
+            .global_set(budget)
+            .global_get(budget)
+            .i32_const(0)
+            .binop(BinaryOp::I32LtS)
+            .if_else(None, |then| {
+                then.unreachable();
+            }, |_| {});
+    });
+}
+
+fn relative(view: &WorldView, x: i32, y: i32) -> (i32, i32) {
+    let (view_x, view_y) = view.position();
+    // Wrapped offsets overflow back out of bounds in `WorldView`.
+    (x.wrapping_sub(view_x), y.wrapping_sub(view_y))
+}
+
+fn host_get(view: &mut WorldView, x: i32, y: i32) -> i32 {
+    let (d_x, d_y) = relative(view, x, y);
+    view.get(d_x, d_y).kind.value()
+}
+
+fn host_set(view: &mut WorldView, x: i32, y: i32, kind: i32) {
+    let kind = match Kind::from_value(kind) {
+        Some(Kind::OutOfBounds) | None => return,
+        Some(kind) => kind,
+    };
+    let (d_x, d_y) = relative(view, x, y);
+    view.set(d_x, d_y, Particle::new(kind));
+}
+
+fn host_swap(view: &mut WorldView, x0: i32, y0: i32, x1: i32, y1: i32) {
+    let (d_x0, d_y0) = relative(view, x0, y0);
+    let (d_x1, d_y1) = relative(view, x1, y1);
+    if view.get(d_x0, d_y0).kind == Kind::OutOfBounds || view.get(d_x1, d_y1).kind == Kind::OutOfBounds {
+        return;
+    }
+    // `swap` moves the particle the view is centered on, so it is a move
+    // rather than a destroy and a place for hooks.
+    let (x, y) = view.position();
+    view.set_viewport(x0, y0);
+    view.swap(x1 - x0, y1 - y0);
+    view.set_viewport(x, y);
+}
+
+/// A material backed by a plugin module, run through `wasmi` natively and
+/// through the browser's own engine on the web.
+pub struct WasmPlugin {
+    #[cfg(not(target_arch = "wasm32"))]
+    instance: wasmi::ModuleRef,
+    #[cfg(target_arch = "wasm32")]
+    instance: browser::Instance,
+}
+
+impl WasmPlugin {
+    pub fn new(bytes: &[u8]) -> Result<Self, PluginError> {
+        validate(bytes)?;
+        let bytes = &meter(bytes)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let instance = native::instantiate(bytes)?;
+        #[cfg(target_arch = "wasm32")]
+        let instance = browser::Instance::new(bytes)
+            .map_err(|error| PluginError::Instantiation(format!("{:?}", error)))?;
+
+        Ok(Self { instance })
+    }
+}
+
+impl<R: Rng> Behavior<R> for WasmPlugin {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        native::update(&self.instance, view, rng);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        self.instance.update(view, rng);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use rand::Rng;
+    use wasmi::{Externals, FuncInstance, FuncRef, ImportsBuilder, ModuleImportResolver, ModuleInstance,
+                ModuleRef, NopExternals, RuntimeArgs, RuntimeValue, Signature, Trap};
+    use crate::engine::WorldView;
+    use super::{host_get, host_set, host_swap, PluginError, IMPORTS};
+
+    struct Resolver;
+
+    impl ModuleImportResolver for Resolver {
+        fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, wasmi::Error> {
+            match IMPORTS.iter().position(|(name, _, _)| *name == field_name) {
+                Some(index) => Ok(FuncInstance::alloc_host(signature.clone(), index)),
+                None => Err(wasmi::Error::Instantiation(format!("unknown import `env.{}`", field_name))),
+            }
+        }
+    }
+
+    struct Host<'a, R> {
+        view: &'a mut WorldView,
+        rng: &'a mut R,
+    }
+
+    impl<'a, R: Rng> Externals for Host<'a, R> {
+        fn invoke_index(&mut self, index: usize, args: RuntimeArgs) -> Result<Option<RuntimeValue>, Trap> {
+            match IMPORTS[index].0 {
+                "get" => {
+                    let kind = host_get(self.view, args.nth_checked(0)?, args.nth_checked(1)?);
+                    Ok(Some(RuntimeValue::I32(kind)))
+                }
+                "set" => {
+                    host_set(self.view, args.nth_checked(0)?, args.nth_checked(1)?, args.nth_checked(2)?);
+                    Ok(None)
+                }
+                "swap" => {
+                    host_swap(self.view, args.nth_checked(0)?, args.nth_checked(1)?,
+                              args.nth_checked(2)?, args.nth_checked(3)?);
+                    Ok(None)
+                }
+                _ => Ok(Some(RuntimeValue::F64(self.rng.gen::<f64>().into()))),
+            }
+        }
+    }
+
+    pub(super) fn instantiate(bytes: &[u8]) -> Result<ModuleRef, PluginError> {
+        let module = wasmi::Module::from_buffer(bytes)
+            .map_err(|error| PluginError::Invalid(error.to_string()))?;
+        let imports = ImportsBuilder::new().with_resolver("env", &Resolver);
+        ModuleInstance::new(&module, &imports)
+            .map_err(|error| PluginError::Instantiation(error.to_string()))?
+            .run_start(&mut NopExternals)
+            .map_err(|error| PluginError::Instantiation(error.to_string()))
+    }
+
+    pub(super) fn update<R: Rng>(instance: &ModuleRef, view: &mut WorldView, rng: &mut R) {
+        let (x, y) = view.position();
+        let args = [RuntimeValue::I32(x), RuntimeValue::I32(y)];
+        // A trapping plugin only loses the update for this cell.
+        let _ = instance.invoke_export("update", &args, &mut Host { view, rng });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod browser {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use rand::{Rng, RngCore};
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use crate::engine::WorldView;
+    use super::{host_get, host_set, host_swap};
+
+    type Current = Rc<RefCell<Option<WorldView>>>;
+    /// The sandbox's rng, only set while `update` runs.
+    type Random = Rc<Cell<Option<*mut dyn RngCore>>>;
+
+    pub(super) struct Instance {
+        update: js_sys::Function,
+        current: Current,
+        random: Random,
+        _get: Closure<dyn FnMut(i32, i32) -> i32>,
+        _set: Closure<dyn FnMut(i32, i32, i32)>,
+        _swap: Closure<dyn FnMut(i32, i32, i32, i32)>,
+        _rand: Closure<dyn FnMut() -> f64>,
+    }
+
+    impl Instance {
+        pub(super) fn new(bytes: &[u8]) -> Result<Self, JsValue> {
+            let current: Current = Rc::new(RefCell::new(None));
+            let random: Random = Rc::new(Cell::new(None));
+
+            let view = current.clone();
+            let get = Closure::wrap(Box::new(move |x, y| {
+                view.borrow_mut().as_mut().map_or(0, |view| host_get(view, x, y))
+            }) as Box<dyn FnMut(i32, i32) -> i32>);
+            let view = current.clone();
+            let set = Closure::wrap(Box::new(move |x, y, kind| {
+                if let Some(view) = view.borrow_mut().as_mut() {
+                    host_set(view, x, y, kind);
+                }
+            }) as Box<dyn FnMut(i32, i32, i32)>);
+            let view = current.clone();
+            let swap = Closure::wrap(Box::new(move |x0, y0, x1, y1| {
+                if let Some(view) = view.borrow_mut().as_mut() {
+                    host_swap(view, x0, y0, x1, y1);
+                }
+            }) as Box<dyn FnMut(i32, i32, i32, i32)>);
+            let rng = random.clone();
+            let rand = Closure::wrap(Box::new(move || {
+                // Safety: `update` only sets the pointer for as long as it
+                // holds the `&mut` it came from.
+                rng.get().map_or(0.0, |rng| unsafe { (*rng).gen::<f64>() })
+            }) as Box<dyn FnMut() -> f64>);
+
+            let env = js_sys::Object::new();
+            js_sys::Reflect::set(&env, &"get".into(), get.as_ref())?;
+            js_sys::Reflect::set(&env, &"set".into(), set.as_ref())?;
+            js_sys::Reflect::set(&env, &"swap".into(), swap.as_ref())?;
+            js_sys::Reflect::set(&env, &"rand".into(), rand.as_ref())?;
+            let imports = js_sys::Object::new();
+            js_sys::Reflect::set(&imports, &"env".into(), &env)?;
+
+            let module = js_sys::WebAssembly::Module::new(&js_sys::Uint8Array::from(bytes).into())?;
+            let instance = js_sys::WebAssembly::Instance::new(&module, &imports)?;
+            let update = js_sys::Reflect::get(&instance.exports(), &"update".into())?;
+
+            Ok(Self {
+                update: update.unchecked_into(),
+                current,
+                random,
+                _get: get,
+                _set: set,
+                _swap: swap,
+                _rand: rand,
+            })
+        }
+
+        pub(super) fn update(&self, view: &WorldView, rng: &mut dyn RngCore) {
+            let (x, y) = view.position();
+            *self.current.borrow_mut() = Some(view.clone());
+            // Erases the borrow's lifetime, it is cleared again below.
+            let rng = unsafe { std::mem::transmute::<*mut (dyn RngCore + '_), *mut dyn RngCore>(rng) };
+            self.random.set(Some(rng));
+            // A trapping plugin only loses the update for this cell.
+            let _ = self.update.call2(&JsValue::NULL, &x.into(), &y.into());
+            self.random.set(None);
+            *self.current.borrow_mut() = None;
+        }
+    }
+}
@@ -0,0 +1,362 @@
+//! Drawing the world, independently of where the pixels end up.
+
+use crate::engine::{Color, Kind, Particle, Sandbox};
+use crate::lighting::Lighting;
+use crate::tools::{self, BrushShape};
+
+/// Draws the world of a sandbox, one frame per call.
+pub trait Renderer {
+    fn render(&mut self, sandbox: &Sandbox);
+}
+
+/// A number per cell that an overlay can show. `value` is given the
+/// particle and the world's clock.
+pub struct Field {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    /// The values labelled in the legend.
+    pub legend: &'static [f32],
+    /// Decimal places in the legend's labels.
+    pub precision: usize,
+    pub value: fn(&Particle, u8) -> f32,
+}
+
+pub static FIELDS: [Field; 3] = [
+    Field {
+        name: "energy",
+        min: 0.0,
+        max: 1.0,
+        legend: &[0.0, 0.25, 0.5, 0.75, 1.0],
+        precision: 2,
+        value: |particle, _| particle.extra.energy(),
+    },
+    // Clocks wrap, so cells look new again after 256 ticks.
+    Field {
+        name: "age",
+        min: 0.0,
+        max: 255.0,
+        legend: &[0.0, 64.0, 128.0, 192.0, 255.0],
+        precision: 0,
+        value: |particle, clock| clock.wrapping_sub(particle.clock) as f32,
+    },
+    Field {
+        name: "activity",
+        min: 0.0,
+        max: 1.0,
+        legend: &[0.0, 1.0],
+        precision: 0,
+        value: |particle, clock| if particle.clock == clock { 1.0 } else { 0.0 },
+    },
+];
+
+/// From low to high, the colors fields are shown in.
+const GRADIENT: [[u8; 3]; 4] = [[20, 20, 90], [0, 160, 230], [250, 210, 0], [255, 255, 255]];
+
+fn gradient(t: f32) -> [u8; 3] {
+    let t = t.max(0.0).min(1.0) * (GRADIENT.len() - 1) as f32;
+    let index = (t as usize).min(GRADIENT.len() - 2);
+    let (from, to, t) = (GRADIENT[index], GRADIENT[index + 1], t - index as f32);
+    let mix = |i: usize| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * t).round() as u8;
+    [mix(0), mix(1), mix(2)]
+}
+
+fn material_color(kind: Kind) -> [u8; 3] {
+    match kind {
+        Kind::Sand => [230, 190, 60],
+        Kind::Plant => [40, 200, 40],
+        Kind::Fire => [240, 50, 20],
+        Kind::Water => [40, 90, 240],
+        Kind::Empty => [0, 0, 0],
+        Kind::OutOfBounds => [128, 128, 128],
+    }
+}
+
+/// What the frame shows for each cell.
+#[derive(Copy, Clone)]
+pub enum Overlay {
+    /// The particles' own colors.
+    Color,
+    /// One flat color per material.
+    Material,
+    /// A field on a gradient, with empty cells left black.
+    Field(&'static Field),
+}
+
+impl Overlay {
+    pub fn all() -> Vec<Overlay> {
+        let mut overlays = vec![Overlay::Color, Overlay::Material];
+        overlays.extend(FIELDS.iter().map(Overlay::Field));
+        overlays
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overlay::Color => "color",
+            Overlay::Material => "material",
+            Overlay::Field(field) => field.name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Overlay> {
+        Self::all().into_iter().find(|overlay| overlay.name() == name)
+    }
+
+    /// Labels and the colors they are shown in. Empty for `Color`.
+    pub fn legend(&self) -> Vec<(String, [u8; 3])> {
+        match self {
+            Overlay::Color => Vec::new(),
+            Overlay::Material => Kind::materials()
+                .map(|kind| (kind.name().to_string(), material_color(kind)))
+                .collect(),
+            Overlay::Field(field) => field.legend.iter()
+                .map(|value| {
+                    let t = (value - field.min) / (field.max - field.min);
+                    (format!("{:.*}", field.precision, value), gradient(t))
+                })
+                .collect(),
+        }
+    }
+
+    fn color(&self, particle: &Particle, clock: u8) -> [u8; 3] {
+        match self {
+            Overlay::Color => {
+                let color = particle.extra.color;
+                [color.r, color.g, color.b]
+            }
+            Overlay::Material => material_color(particle.kind),
+            Overlay::Field(_) if particle.kind == Kind::Empty => [0, 0, 0],
+            Overlay::Field(field) => {
+                let value = (field.value)(particle, clock);
+                gradient((value - field.min) / (field.max - field.min))
+            }
+        }
+    }
+
+    /// Whether a cell's color only changes when the cell is written.
+    fn per_particle(&self) -> bool {
+        match self {
+            Overlay::Color | Overlay::Material => true,
+            Overlay::Field(_) => false,
+        }
+    }
+}
+
+/// An RGBA image of the world with one pixel per cell, drawn on the CPU.
+/// The buffer is reused between frames.
+pub struct FrameBuffer {
+    width: i32,
+    height: i32,
+    data: Vec<u8>,
+    overlay: Overlay,
+    lighting: Option<Lighting>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![255; (width * height * 4) as usize],
+            overlay: Overlay::Color,
+            lighting: None,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The pixels, row by row, four bytes each.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> [u8; 4] {
+        let index = ((x + y * self.width) * 4) as usize;
+        [self.data[index], self.data[index + 1], self.data[index + 2], self.data[index + 3]]
+    }
+
+    pub fn overlay(&self) -> Overlay {
+        self.overlay
+    }
+
+    /// Takes effect from the next full redraw.
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = overlay;
+    }
+
+    pub fn lighting(&self) -> bool {
+        self.lighting.is_some()
+    }
+
+    /// Turns the lighting pass on or off. It only lights particle colors, not
+    /// other overlays. Takes effect from the next full redraw. It is off by
+    /// default since it makes every tick redraw and relight the whole frame,
+    /// see `draw_written`.
+    pub fn set_lighting(&mut self, lighting: bool) {
+        if lighting != self.lighting() {
+            self.lighting = if lighting { Some(Lighting::new()) } else { None };
+        }
+    }
+
+    pub(crate) fn resize(&mut self, width: i32, height: i32) {
+        if (self.width, self.height) != (width, height) {
+            let lighting = self.lighting.take();
+            *self = Self { overlay: self.overlay, lighting, ..Self::new(width, height) };
+        }
+    }
+
+    /// Redraws every cell.
+    pub(crate) fn draw(&mut self, particles: &[Particle], clock: u8) {
+        let overlay = self.overlay;
+        for (pixel, particle) in self.data.chunks_exact_mut(4).zip(particles) {
+            pixel[..3].copy_from_slice(&overlay.color(particle, clock));
+        }
+        if let (Overlay::Color, Some(lighting)) = (overlay, &mut self.lighting) {
+            lighting.apply(&mut self.data, particles, self.width, self.height);
+        }
+    }
+
+    /// Redraws the cells written at `clock`, which every write stamps, or
+    /// every cell if the overlay or lighting changes cells that weren't
+    /// written.
+    pub(crate) fn draw_written(&mut self, particles: &[Particle], clock: u8) {
+        let overlay = self.overlay;
+        if !overlay.per_particle() || self.lighting() {
+            return self.draw(particles, clock);
+        }
+        for (pixel, particle) in self.data.chunks_exact_mut(4).zip(particles) {
+            if particle.clock == clock {
+                pixel[..3].copy_from_slice(&overlay.color(particle, clock));
+            }
+        }
+    }
+}
+
+/// Where the world is drawn on screen: each cell is `zoom` screen pixels
+/// wide, and the world's origin sits at `(x, y)` in screen pixels. Zoom is a
+/// whole number so cells stay square and sharp.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub zoom: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Camera {
+    pub const MAX_ZOOM: i32 = 32;
+
+    pub fn new() -> Self {
+        Self { zoom: 1, x: 0, y: 0 }
+    }
+
+    /// The cell under a screen position.
+    pub fn to_world(&self, screen_x: i32, screen_y: i32) -> (i32, i32) {
+        (
+            (screen_x - self.x).div_euclid(self.zoom),
+            (screen_y - self.y).div_euclid(self.zoom),
+        )
+    }
+
+    /// The screen position of a cell's top left corner.
+    pub fn to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        (x * self.zoom + self.x, y * self.zoom + self.y)
+    }
+
+    pub fn pan(&mut self, d_x: i32, d_y: i32) {
+        self.x += d_x;
+        self.y += d_y;
+    }
+
+    /// Zooms in or out by `steps` levels, keeping the cell under the screen
+    /// position where it is.
+    pub fn zoom_at(&mut self, screen_x: i32, screen_y: i32, steps: i32) {
+        let zoom = (self.zoom + steps).max(1).min(Self::MAX_ZOOM);
+        let (x, y) = self.to_world(screen_x, screen_y);
+        self.x -= x * (zoom - self.zoom);
+        self.y -= y * (zoom - self.zoom);
+        self.zoom = zoom;
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The cells a brush would paint at the pointer, drawn over the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrushPreview {
+    pub x: i32,
+    pub y: i32,
+    pub shape: BrushShape,
+    pub radius: i32,
+    pub color: Color,
+}
+
+impl BrushPreview {
+    /// The preview for painting `kind` with `tool` at `(x, y)`. Erasing is
+    /// shown in grey, which stays visible over empty cells.
+    pub fn new(x: i32, y: i32, kind: Kind, size: u32, tool: Option<&str>) -> Self {
+        let (shape, radius) = tools::footprint(tool, size);
+        let color = match kind {
+            Kind::Empty => Color { r: 200, g: 200, b: 200 },
+            _ => kind.color(),
+        };
+        Self { x, y, shape, radius, color }
+    }
+
+    /// How far a row `d_y` from the center reaches to either side, or `-1`
+    /// outside the brush.
+    fn extent(&self, d_y: i32) -> i32 {
+        if d_y.abs() > self.radius {
+            return -1;
+        }
+        match self.shape {
+            BrushShape::Square => self.radius,
+            // Square roots of whole squares are exact, so this rounds down
+            // to the last cell inside the circle.
+            BrushShape::Circle => ((self.radius * self.radius - d_y * d_y) as f64).sqrt() as i32,
+        }
+    }
+
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        (x - self.x).abs() <= self.extent(y - self.y)
+    }
+
+    /// The cells on the edge of the brush, as `(x, y, length)` runs along
+    /// rows.
+    pub fn outline(&self) -> Vec<(i32, i32, i32)> {
+        let mut runs = Vec::new();
+        for d_y in -self.radius..=self.radius {
+            let extent = self.extent(d_y);
+            // Cells beyond the rows above and below are on the edge.
+            let inner = self.extent(d_y - 1).min(self.extent(d_y + 1)).min(extent - 1);
+            let y = self.y + d_y;
+            if inner < 0 {
+                runs.push((self.x - extent, y, 2 * extent + 1));
+            } else {
+                runs.push((self.x - extent, y, extent - inner));
+                runs.push((self.x + inner + 1, y, extent - inner));
+            }
+        }
+        runs
+    }
+}
+
+impl Renderer for FrameBuffer {
+    fn render(&mut self, sandbox: &Sandbox) {
+        self.resize(sandbox.width(), sandbox.height());
+        self.draw(&sandbox.particles(), sandbox.clock());
+    }
+}
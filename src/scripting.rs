@@ -1,291 +1,691 @@
-use rhai::{Engine, EvalAltResult, Scope, RegisterFn, AST};
-use crate::engine::{EMPTY, Particle, WorldView, Kind};
-use rand::{thread_rng, Rng};
-use rand::prelude::ThreadRng;
-use wasm_bindgen::prelude::*;
-use walrus::ir::*;
-use walrus::{FunctionBuilder, Module, ModuleConfig, ValType};
-use wasm_bindgen::JsCast;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope, RegisterFn, RegisterResultFn, AST,
+           ParseError, ParseErrorType, Position, FLOAT, INT};
+use crate::backend::{Host, ScriptBackend};
+use crate::engine::{EMPTY, Particle, UserEvent, WorldView, Kind};
+use crate::generators::{self, GeneratorWorld};
+use crate::events::{Event, Hook};
+use crate::noise::Noise;
+use crate::tools;
+use crate::patterns::{Pattern, PatternRules};
+use crate::snapshot::{Reader, SnapshotError, Writer};
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::rc::Rc;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
 
-pub struct ScriptEngine {
-    engine: Engine,
-    script: AST,
+pub(crate) static SAND: &str = r"
+    if view.kind(0, 1) == KIND_EMPTY {
+        view.swap(0, 1);
+    } else if rng.gen_bool(0.5) {
+        if view.kind(-1, 1) == KIND_EMPTY {
+            view.swap(-1, 1);
+        }
+    } else if view.kind(1, 1) == KIND_EMPTY {
+        view.swap(1, 1);
+    }
+";
+
+/// Operations one run of a material script or hook may take before it is
+/// stopped with an error.
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// Tools loop over their brush, so on top of `MAX_OPERATIONS` they get this
+/// many for each cell of the square a brush of their size spans.
+pub const MAX_TOOL_OPERATIONS_PER_CELL: u64 = 1_000;
+
+/// Generators run once over the whole world, so they get a bigger budget.
+pub const MAX_GENERATOR_OPERATIONS: u64 = 100_000_000;
+
+/// Handle to the sandbox's random number generator, shared with scripts.
+#[derive(Clone)]
+pub(crate) struct ScriptRng(Rc<RefCell<StdRng>>);
+
+impl ScriptRng {
+    /// A runtime error for `p` outside `0.0..=1.0`, which would panic `rand`.
+    pub(crate) fn gen_bool(&mut self, p: f64) -> Result<bool, Box<EvalAltResult>> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(format!("gen_bool: probability {} is not between 0.0 and 1.0", p).into());
+        }
+        Ok(self.0.borrow_mut().gen_bool(p))
+    }
+
+    pub(crate) fn borrow_mut(&self) -> RefMut<'_, StdRng> {
+        self.0.borrow_mut()
+    }
 }
 
-impl ScriptEngine {
-    pub(crate) fn new() -> Self {
-        let mut engine = Engine::new();
+/// Variables that outlive a tick: `global` is shared by every script, and each
+/// material's scripts get their own `state`.
+#[derive(Clone, Default)]
+pub(crate) struct ScriptState(Rc<RefCell<Map>>);
 
-        engine.register_type::<WorldView>();
+impl ScriptState {
+    fn get(&mut self, key: ImmutableString) -> Dynamic {
+        self.0.borrow().get(key.as_str()).cloned().unwrap_or_else(|| Dynamic::from(()))
+    }
 
-        engine.register_fn("get", WorldView::get);
-        engine.register_fn("set", WorldView::set);
-        engine.register_fn("set_viewport", WorldView::set_viewport);
+    fn contains(&mut self, key: ImmutableString) -> bool {
+        self.0.borrow().contains_key(key.as_str())
+    }
 
-        engine.register_type::<Particle>();
+    fn remove(&mut self, key: ImmutableString) {
+        self.0.borrow_mut().remove(key.as_str());
+    }
 
-        engine.register_get("kind", Particle::get_kind);
-        engine.register_get("clock", Particle::get_clock);
+    pub(crate) fn value(&self, key: &str) -> Option<Dynamic> {
+        self.0.borrow().get(key).cloned()
+    }
 
-        engine.register_type::<ThreadRng>();
-        engine.register_fn("gen_bool", ThreadRng::gen_bool);
-
-        // Construct a new Walrus module.
-        let config = ModuleConfig::new();
-        let mut module = Module::with_config(config);
-
-        // Building this factorial implementation:
-        // https://github.com/WebAssembly/testsuite/blob/7816043/fac.wast#L46-L66
-        let mut factorial = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
-
-        // Create our paramter and our two locals.
-        let n = module.locals.add(ValType::I32);
-        let i = module.locals.add(ValType::I32);
-        let res = module.locals.add(ValType::I32);
-
-        factorial
-            // Enter the function's body.
-            .func_body()
-            // (local.set $i (local.get $n))
-            .local_get(n)
-            .local_set(i)
-            // (local.set $res (i32.const 1))
-            .i32_const(100)
-            .local_set(res)
-            .block(None, |done| {
-                let done_id = done.id();
-                done.loop_(None, |loop_| {
-                    let loop_id = loop_.id();
-                    loop_
-                        // (i32.eq (local.get $i) (i32.const 0))
-                        .local_get(i)
-                        .i32_const(0)
-                        .binop(BinaryOp::I32Eq)
-                        .if_else(
-                            None,
-                            |then| {
-                                // (then (br $done))
-                                then.br(done_id);
-                            },
-                            |else_| {
-                                else_
-                                    // (local.set $res (i32.mul (local.get $i) (local.get $res)))
-                                    .i32_const(100)
-                                    .local_get(res)
-                                    .binop(BinaryOp::I32Mul)
-                                    .local_set(res)
-                                    // (local.set $i (i32.sub (local.get $i) (i32.const 1))))
-                                    .local_get(i)
-                                    .i32_const(1)
-                                    .binop(BinaryOp::I32Sub)
-                                    .local_set(i);
-                            },
-                        )
-                        .br(loop_id);
-                });
-            })
-            .local_get(res);
-
-        let factorial = factorial.finish(vec![n], &mut module.funcs);
-
-        // Export the `factorial` function.
-        module.exports.add("factorial", factorial);
-
-        // Faster way than doing reflection
-        // https://github.com/rustwasm/wasm-bindgen/issues/1428
-        let wasm_bytes = module.emit_wasm();
-        let descriptor = &js_sys::Object::new();
-        js_sys::Reflect::set(&descriptor, &"initial".into(), &JsValue::from(256)).unwrap();
-        js_sys::Reflect::set(&descriptor, &"maximum".into(), &JsValue::from(256)).unwrap();
-        let memory = js_sys::WebAssembly::Memory::new(&descriptor).unwrap();
-        let import_object = &js_sys::Object::new();
-        let env_object = &js_sys::Object::new();
-        let table_descriptor = &js_sys::Object::new();
-        js_sys::Reflect::set(&table_descriptor, &"initial".into(), &JsValue::from(0)).unwrap();
-        js_sys::Reflect::set(&table_descriptor, &"maximum".into(), &JsValue::from(0)).unwrap();
-        js_sys::Reflect::set(&table_descriptor, &"element".into(), &JsValue::from("anyfunc")).unwrap();
-        js_sys::Reflect::set(&env_object, &"table".into(),
-                             &js_sys::WebAssembly::Table::new(&table_descriptor).unwrap()).unwrap();
-        js_sys::Reflect::set(&env_object, &"tableBase".into(), &JsValue::from(0)).unwrap();
-        js_sys::Reflect::set(&env_object, &"memory".into(), &memory).unwrap();
-        js_sys::Reflect::set(&env_object, &"memoryBase".into(), &JsValue::from(1024)).unwrap();
-        js_sys::Reflect::set(&env_object, &"STACKTOP".into(), &JsValue::from(0)).unwrap();
-        js_sys::Reflect::set(&env_object, &"STACK_MAX".into(), &JsValue::from(256)).unwrap();
-        js_sys::Reflect::set(&import_object, &"env".into(), env_object).unwrap();
-
-        let promise = js_sys::WebAssembly::instantiate_buffer(&*wasm_bytes, import_object);
-        let closure = Closure::wrap(Box::new(move |result| {
-            let value = js_sys::Reflect::get(
-                &result,
-                &JsValue::from_str("instance")).unwrap();
-            web_sys::console::log_1(&value);
-            let exports = js_sys::Reflect::get(&value, &"exports".into()).unwrap();
-            let factorial = js_sys::Reflect::get(&exports, &"factorial".into()).unwrap();
-            let factorial = factorial.unchecked_into::<js_sys::Function>();
-            let result = js_sys::Reflect::apply(&factorial, &JsValue::null(), &js_sys::Array::new()).unwrap();
-            web_sys::console::log_1(&result);
-        }) as Box<dyn FnMut(_)>);
-
-        let _ = promise.then(&closure);
-
-        // Note: This leaks memory, don't want to do this on every compliation.
-        closure.forget();
-
-        // js_sys::WebAssembly::compile()
-        let script = engine.compile(r"
-            for x in range(0, width) {
-                let x = if clock % 2 == 0 {
-                    width - (1 + x)
-                } else {
-                    x
-                };
-
-                for y in range(0, height) {
-                    view.set_viewport(x, y);
-                    let current = view.get(0, 0);
-                    if current.kind == KIND_EMPTY || current.clock == clock {
-                        continue;
-                    }
+    pub(crate) fn insert(&self, key: &str, value: Dynamic) {
+        self.0.borrow_mut().insert(key.to_string(), value);
+    }
+}
 
-                    if current.kind == KIND_SAND {
-                        let dx = if rng.gen_bool(0.5) { -1 } else { 1 };
-                        let side = view.get(dx, 1);
-                        let below = view.get(0, 1);
-                        if below.kind == KIND_EMPTY {
-                            view.set(0, 1, current);
-                            view.set(0, 0, EMPTY);
-                        } else if side.kind == KIND_EMPTY {
-                            view.set(dx, 1, current);
-                            view.set(0, 0, EMPTY);
-                        } else {
-                            view.set(0, 0, current);
-                        }
-                    }
+// Rhai can't dispatch on `Dynamic` arguments, so `get` with a default and
+// `set` are registered once per storable type.
+macro_rules! register_state_accessors {
+    ($engine:ident, $($ty:ty),*) => {
+        $(
+            $engine.register_fn("get", |state: &mut ScriptState, key: ImmutableString, default: $ty| {
+                state.0.borrow().get(key.as_str()).cloned().unwrap_or_else(|| Dynamic::from(default))
+            });
+            $engine.register_fn("set", |state: &mut ScriptState, key: ImmutableString, value: $ty| {
+                state.0.borrow_mut().insert(key.to_string(), Dynamic::from(value));
+            });
+        )*
+    };
+}
+
+fn pattern_rows(value: Dynamic) -> Result<Vec<String>, Box<EvalAltResult>> {
+    if let Ok(rows) = value.as_str() {
+        return Ok(rows.split_whitespace().map(String::from).collect());
+    }
+    match value.try_cast::<Array>() {
+        Some(rows) => rows.into_iter()
+            .map(|row| row.as_str().map(String::from).map_err(|_| "pattern rows must be strings".into()))
+            .collect(),
+        None => Err("patterns must be a string or an array of strings".into()),
+    }
+}
+
+fn define_pattern(patterns: &RefCell<Vec<Pattern>>, matches: Dynamic, replaces: Dynamic, options: Map)
+                  -> Result<(), Box<EvalAltResult>> {
+    let matches = pattern_rows(matches)?;
+    let replaces = pattern_rows(replaces)?;
+    let matches: Vec<&str> = matches.iter().map(String::as_str).collect();
+    let replaces: Vec<&str> = replaces.iter().map(String::as_str).collect();
+    let mut pattern = Pattern::new(&matches, &replaces)?;
+
+    for (key, value) in options {
+        match key.as_str() {
+            "probability" => {
+                pattern.probability = value.as_float()
+                    .or_else(|_| value.as_int().map(|value| value as f64))
+                    .map_err(|_| "`probability` must be a number")?;
+                if !(0.0..=1.0).contains(&pattern.probability) {
+                    return Err("`probability` must be between 0.0 and 1.0".into());
                 }
             }
-        ").unwrap();
-
-        Self {
-            engine,
-            script,
+            "mirror_x" => pattern.mirror_x = value.as_bool().map_err(|_| "`mirror_x` must be a bool")?,
+            "mirror_y" => pattern.mirror_y = value.as_bool().map_err(|_| "`mirror_y` must be a bool")?,
+            _ => return Err(format!("unknown pattern option `{}`", key).into()),
         }
     }
 
-    pub(crate) fn tick(&mut self, clock: u8, width: i32, height: i32, view: WorldView) -> Result<(), Box<EvalAltResult>> {
-        /*for x in 0..self.width {
-            let x = if clock % 2 == 0 {
-                self.width - (1 + x)
-            } else {
-                x
-            };
+    patterns.borrow_mut().push(pattern);
+    Ok(())
+}
 
-            for y in 0..self.height {
-                let current = self.world.borrow().get(x, y);
-                if current.kind == Kind::Empty || current.clock == clock {
-                    continue;
+/// Rewrites `view.set(...)` and `view.swap(...)` to pass their line number, so
+/// writes can be traced back to the script. Lines keep their numbering.
+///
+/// This is a textual rewrite, not a parse: calls through another name, like
+/// `let v = view; v.set(...)`, or spaced out, like `view .set (...)`, are
+/// left alone and traced without a line.
+fn instrument(script: &str) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    script.split('\n').enumerate().map(|(index, line)| {
+        let mut instrumented = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find("view.") {
+            let call = &rest[start + "view.".len()..];
+            let name = ["set(", "swap("].iter().find(|name| call.starts_with(*name));
+            let preceded = rest[..start].chars().next_back().map_or(false, is_ident);
+            instrumented.push_str(&rest[..start]);
+            match name {
+                Some(name) if !preceded => {
+                    let name = &name[..name.len() - 1];
+                    instrumented.push_str(&format!("view.{}_at({}, ", name, index + 1));
+                    rest = &call[name.len() + 1..];
                 }
+                _ => {
+                    instrumented.push_str("view.");
+                    rest = call;
+                }
+            }
+        }
+        instrumented.push_str(rest);
+        instrumented
+    }).collect::<Vec<_>>().join("\n")
+}
 
-                let mut view = WorldView {
-                    x,
-                    y,
-                    world: self.world.clone(),
-                };
-
-                match current.kind {
-                    Kind::Sand => {
-                        self.script_engine.run(view, current.kind).unwrap();
-                    }
-                    Kind::Plant => {
-                        /*
-                        if rng.gen_bool(((current.extra.energy * 0.05) + 0.05) as f64) {
-                            let cost = 0.02;
-                            let mut growth_spots = [
-                                (-1, -1), (1, -1), (-1, 0), (1, 0), (0, -1)];
-                            growth_spots.shuffle(&mut rng);
-                            let mut grown = false;
-
-                            let mut nearby = 0;
-                            for x in -2..=2 {
-                                for y in -2..=2 {
-                                    if view.get(x, y).kind == Kind::Plant {
-                                        nearby += 1;
-                                    }
-                                }
-                            }
+/// Rhai panics on a `.` followed by anything but a name, like `a.1` or
+/// `a.(b)`, so those are rejected before it sees them.
+fn check_properties(script: &str) -> Result<(), ParseError> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let chars: Vec<char> = script.chars().collect();
+    let (mut line, mut column) = (1, 0);
+    let mut index = 0;
 
-                            for point in growth_spots.iter() {
-                                let spot = view.get(point.0, point.1);
-                                if spot.kind == Kind::Empty && nearby <= 20 && current.extra.energy > 0.0 && !grown {
-                                    view.set(point.0, point.1, current.with_energy(current.extra.energy - cost).new_extra());
-                                    grown = true;
-                                } else if spot.kind == Kind::Water {
-                                    view.set(point.0, point.1, current.with_energy(1.0).new_extra());
-                                    grown = true;
-                                }
-                            }
-                            if grown {
-                                view.set(0, 0, current.with_energy(0.0));
-                            } else {
-                                view.set(0, 0, current.with_energy(current.extra.energy - cost / 2.0));
-                            }
-                        } else {
-                            view.set(0, 0, current);
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        let end = match c {
+            '"' | '\'' => {
+                let mut end = index + 1;
+                while end < chars.len() && chars[end] != c {
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                end + 1
+            }
+            '/' if next == Some('/') => {
+                chars[index..].iter().position(|&c| c == '\n').map_or(chars.len(), |end| index + end)
+            }
+            '/' if next == Some('*') => {
+                let mut depth = 0;
+                let mut end = index;
+                while end < chars.len() {
+                    match (chars[end], chars.get(end + 1)) {
+                        ('/', Some('*')) => {
+                            depth += 1;
+                            end += 2;
                         }
-                    }
-                    Kind::Fire => {
-                        if current.extra.energy <= 0.0 {
-                            view.set(0, 0, EMPTY);
-                        } else {
-                            let cost = 0.1;
-                            view.set(0, 0, current.with_energy(current.extra.energy - cost));
-                            let dx = rng.gen_range(-1, 2);
-                            let dy = rng.gen_range(-1, 2);
-                            if dx != 0 || dy != 0 {
-                                let next = view.get(dx, dy);
-                                if next.kind == Kind::Empty {
-                                    view.set(dx, dy, current.with_energy(current.extra.energy - cost));
-                                } else if next.kind == Kind::Plant {
-                                    view.set(dx, dy, current.with_energy(1.0));
-                                }
+                        ('*', Some('/')) => {
+                            depth -= 1;
+                            end += 2;
+                            if depth == 0 {
+                                break;
                             }
                         }
+                        _ => end += 1,
                     }
-                    Kind::Water => {
-                        let dx = if rng.gen_bool(0.5) { -1 } else { 1 };
-                        let side = view.get(dx, 1);
-                        let below = view.get(0, 1);
-                        if below.kind == Kind::Empty || below.kind == Kind::Fire {
-                            view.set(0, 1, current);
-                            view.set(0, 0, EMPTY);
-                        } else if side.kind == Kind::Empty || below.kind == Kind::Fire {
-                            view.set(dx, 1, current);
-                            view.set(0, 0, EMPTY);
-                        } else {
-                            view.set(0, 0, current);
-                        }
-                    }
-                    _ => view.set(0, 0, current),
                 }
-                 */
+                end
             }
-         */
-        let mut scope = Scope::new();
-        let rng = thread_rng();
+            // Numbers, including the `.` of a float.
+            '0'..='9' => {
+                let mut end = index;
+                while end < chars.len() && (is_ident(chars[end])
+                    || chars[end] == '.' && chars.get(end + 1).map_or(false, char::is_ascii_digit)) {
+                    end += 1;
+                }
+                end
+            }
+            '.' => {
+                let name = chars[index + 1..].iter().find(|c| !c.is_whitespace());
+                if !name.map_or(false, |&c| c.is_alphabetic() || c == '_') {
+                    let position = Position::new(line.min(u16::MAX as usize) as u16,
+                                                 (column + 1).min(u16::MAX as usize) as u16);
+                    return Err(ParseError(Box::new(ParseErrorType::PropertyExpected), position));
+                }
+                index + 1
+            }
+            c if is_ident(c) => {
+                chars[index..].iter().position(|&c| !is_ident(c)).map_or(chars.len(), |end| index + end)
+            }
+            _ => index + 1,
+        };
+
+        let end = end.min(chars.len());
+        for &c in &chars[index..end] {
+            if c == '\n' {
+                line += 1;
+                column = 0;
+            } else {
+                column += 1;
+            }
+        }
+        index = end;
+    }
+    Ok(())
+}
+
+/// Names declared with `let` anywhere in `script`.
+fn declared_names(script: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut words = script.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty());
+    while let Some(word) = words.next() {
+        if word == "let" {
+            if let Some(name) = words.next() {
+                if !names.iter().any(|other| other == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+/// A material script compiled by `RhaiBackend`.
+pub struct RhaiScript {
+    source: String,
+    ast: AST,
+    locals: Vec<String>,
+}
+
+/// The default backend, running scripts through Rhai. The sandbox's `rng`,
+/// `clock` and `global` stay in its scope between runs.
+pub struct RhaiBackend {
+    engine: Engine,
+    scope: Scope<'static>,
+    debug: bool,
+}
+
+impl RhaiBackend {
+    fn new(rng: ScriptRng, global: ScriptState) -> (Self, Rc<RefCell<Vec<Pattern>>>) {
+        let mut engine = Engine::new();
+
+        // Scripts are untrusted, so runaway loops, deep recursion and huge
+        // values fail instead of hanging the page or exhausting memory.
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(10_000);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+
+        engine.register_type::<WorldView>();
+
+        engine.register_fn("get", WorldView::get);
+        engine.register_fn("kind", WorldView::get_kind);
+        engine.register_fn("set", WorldView::set);
+        engine.register_fn("swap", WorldView::swap);
+        engine.register_fn("set_at", WorldView::set_at);
+        engine.register_fn("swap_at", WorldView::swap_at);
+        engine.register_fn("set_viewport", WorldView::set_viewport);
+
+        engine.register_type::<Particle>();
+
+        engine.register_get("kind", Particle::get_kind);
+        engine.register_get("clock", Particle::get_clock);
+
+        engine.register_type::<ScriptRng>();
+        engine.register_result_fn("gen_bool", |rng: &mut ScriptRng, p: f64| {
+            rng.gen_bool(p).map(Dynamic::from)
+        });
 
+        engine.register_type::<ScriptState>();
+        engine.register_fn("get", ScriptState::get);
+        engine.register_fn("contains", ScriptState::contains);
+        engine.register_fn("remove", ScriptState::remove);
+        register_state_accessors!(engine, INT, FLOAT, bool, char, ImmutableString, Array, Map);
+
+        generators::register(&mut engine);
+        tools::register(&mut engine);
+
+        // Patterns can be written as one string, with rows separated by spaces,
+        // or as an array of rows.
+        let patterns = Rc::new(RefCell::new(Vec::new()));
+        let defined = patterns.clone();
+        engine.register_result_fn("rule", move |matches: ImmutableString, replaces: ImmutableString| {
+            define_pattern(&defined, matches.into(), replaces.into(), Map::new()).map(Dynamic::from)
+        });
+        let defined = patterns.clone();
+        engine.register_result_fn("rule", move |matches: ImmutableString, replaces: ImmutableString, options: Map| {
+            define_pattern(&defined, matches.into(), replaces.into(), options).map(Dynamic::from)
+        });
+        let defined = patterns.clone();
+        engine.register_result_fn("rule", move |matches: Array, replaces: Array| {
+            define_pattern(&defined, matches.into(), replaces.into(), Map::new()).map(Dynamic::from)
+        });
+        let defined = patterns.clone();
+        engine.register_result_fn("rule", move |matches: Array, replaces: Array, options: Map| {
+            define_pattern(&defined, matches.into(), replaces.into(), options).map(Dynamic::from)
+        });
+
+        let mut scope = Scope::new();
         scope.push_constant("KIND_SAND", Kind::Sand.value());
         scope.push_constant("KIND_EMPTY", Kind::Empty.value());
         scope.push_constant("KIND_WATER", Kind::Water.value());
         scope.push_constant("KIND_FIRE", Kind::Fire.value());
         scope.push_constant("KIND_PLANT", Kind::Plant.value());
+        scope.push_constant("KIND_OUT_OF_BOUNDS", Kind::OutOfBounds.value());
         scope.push_constant("EMPTY", EMPTY);
         scope.push("rng", rng);
-        scope.push("clock", clock as i32);
-        scope.push("width", width);
-        scope.push("height", height);
-        scope.push("view", view);
+        scope.push("clock", 0);
+        scope.push("global", global);
+
+        (Self {
+            engine,
+            scope,
+            debug: false,
+        }, patterns)
+    }
+
+    /// `Engine::compile`, first rejecting what Rhai can't parse safely.
+    fn compile_ast(&self, source: &str) -> Result<AST, ParseError> {
+        check_properties(source)?;
+        self.engine.compile(source)
+    }
+
+    /// Runs `script` and returns its top-level `let` variables as they were
+    /// at the end. Variables declared in blocks are gone by then, and ones
+    /// shadowing the sandbox's own are left out.
+    fn locals(&mut self, script: &RhaiScript, host: &mut Host) -> Vec<(String, String)> {
+        let outer = self.scope.clone();
+        let len = self.scope.len();
+        self.scope.push("state", host.state.clone());
+        self.scope.push("current", host.current);
+        self.scope.push("view", host.view.clone());
+
+        let _ = self.engine.eval_ast_with_scope::<()>(&mut self.scope, &script.ast);
+        let scope = &self.scope;
+        let locals = script.locals.iter()
+            .filter(|name| !outer.contains(name) && !["state", "current", "view"].contains(&name.as_str()))
+            .filter_map(|name| scope.get_value::<Dynamic>(name).map(|value| (name.clone(), value.to_string())))
+            .collect();
+        self.scope.rewind(len);
+
+        locals
+    }
+
+    fn instrument(&self, script: &mut RhaiScript) {
+        // Rewriting can only break scripts that use `view.set` in odd ways;
+        // those keep running uninstrumented, just without line numbers.
+        if let Ok(ast) = self.compile_ast(&instrument(&script.source)) {
+            script.ast = ast;
+        }
+    }
+}
+
+impl ScriptBackend for RhaiBackend {
+    type Script = RhaiScript;
+    type Error = Box<EvalAltResult>;
+
+    fn compile(&mut self, source: &str) -> Result<RhaiScript, Box<EvalAltResult>> {
+        let mut script = RhaiScript {
+            source: source.to_string(),
+            ast: self.compile_ast(source)?,
+            locals: declared_names(source),
+        };
+        if self.debug {
+            self.instrument(&mut script);
+        }
+        Ok(script)
+    }
+
+    fn run(&mut self, script: &RhaiScript, host: &mut Host) -> Result<(), Box<EvalAltResult>> {
+        let len = self.scope.len();
+        self.scope.push("state", host.state.clone());
+        self.scope.push("current", host.current);
+        self.scope.push("view", host.view.clone());
+
+        let result = self.engine.eval_ast_with_scope::<()>(&mut self.scope, &script.ast);
+        self.scope.rewind(len);
+
+        result
+    }
+}
+
+type Run = Box<dyn Fn(&mut Host) -> Result<(), String>>;
+
+pub struct ScriptEngine {
+    rhai: RhaiBackend,
+    scripts: HashMap<Kind, RhaiScript>,
+    // Scripts from other backends, which only run for cells.
+    foreign: HashMap<Kind, Run>,
+    tools: HashMap<String, AST>,
+    hooks: HashMap<(Kind, Hook), AST>,
+    patterns: Rc<RefCell<Vec<Pattern>>>,
+    rng: ScriptRng,
+    clock: u8,
+    global: ScriptState,
+    states: HashMap<Kind, ScriptState>,
+}
+
+impl ScriptEngine {
+    pub(crate) fn new(rng: Rc<RefCell<StdRng>>) -> Self {
+        let rng = ScriptRng(rng);
+        let global = ScriptState::default();
+        let (rhai, patterns) = RhaiBackend::new(rng.clone(), global.clone());
+
+        Self {
+            rhai,
+            scripts: HashMap::new(),
+            foreign: HashMap::new(),
+            tools: HashMap::new(),
+            hooks: HashMap::new(),
+            patterns,
+            rng,
+            clock: 0,
+            global,
+            states: HashMap::new(),
+        }
+    }
 
-        self.engine.eval_ast_with_scope(&mut scope, &self.script)?;
+    pub(crate) fn set_script(&mut self, kind: Kind, script: &str) -> Result<(), ParseError> {
+        let script = self.rhai.compile(script).map_err(|error| match *error {
+            EvalAltResult::ErrorParsing(error, position) => ParseError(Box::new(error), position),
+            error => unreachable!("compiling can only fail to parse: {}", error),
+        })?;
+        self.foreign.remove(&kind);
+        self.scripts.insert(kind, script);
+        Ok(())
+    }
 
+    /// Compiles `source` with another backend and runs it for every cell of
+    /// `kind`, replacing any script.
+    pub(crate) fn set_foreign_script<B: ScriptBackend + 'static>(&mut self, kind: Kind, backend: Rc<RefCell<B>>,
+                                                               source: &str) -> Result<(), B::Error> {
+        let script = backend.borrow_mut().compile(source)?;
+        let run = move |host: &mut Host| backend.borrow_mut().run(&script, host).map_err(|error| error.to_string());
+        self.scripts.remove(&kind);
+        self.foreign.insert(kind, Box::new(run));
         Ok(())
     }
+
+    /// In debug mode, scripts report the line of every write.
+    pub(crate) fn set_debug(&mut self, debug: bool) {
+        if self.rhai.debug == debug {
+            return;
+        }
+        self.rhai.debug = debug;
+        for script in self.scripts.values_mut() {
+            if debug {
+                self.rhai.instrument(script);
+            } else {
+                script.ast = self.rhai.compile_ast(&script.source).unwrap();
+            }
+        }
+    }
+
+    pub(crate) fn has_script(&self, kind: Kind) -> bool {
+        self.scripts.contains_key(&kind) || self.foreign.contains_key(&kind)
+    }
+
+    /// Runs `script` once, collecting the patterns it declares with `rule`.
+    pub(crate) fn define_patterns(&mut self, script: &str) -> Result<PatternRules, Box<EvalAltResult>> {
+        self.patterns.borrow_mut().clear();
+        let script = self.rhai.compile_ast(script)?;
+
+        let len = self.rhai.scope.len();
+        let result = self.rhai.engine.consume_ast_with_scope(&mut self.rhai.scope, &script);
+        self.rhai.scope.rewind(len);
+        result?;
+
+        Ok(PatternRules::new(self.patterns.borrow_mut().drain(..).collect()))
+    }
+
+    /// Runs the generator `script` once over `world`, which must be centered
+    /// on (0, 0).
+    pub(crate) fn generate(&mut self, script: &str, world: WorldView, noise: Noise) -> Result<(), Box<EvalAltResult>> {
+        let script = self.rhai.compile_ast(script)?;
+        let len = self.rhai.scope.len();
+        self.rhai.scope.push("world", GeneratorWorld(world));
+        self.rhai.scope.push("noise", noise);
+        self.rhai.engine.set_max_operations(MAX_GENERATOR_OPERATIONS);
+        let result = self.rhai.engine.consume_ast_with_scope(&mut self.rhai.scope, &script);
+        self.rhai.engine.set_max_operations(MAX_OPERATIONS);
+        self.rhai.scope.rewind(len);
+        result
+    }
+
+    pub(crate) fn set_tool(&mut self, name: &str, script: &str) -> Result<(), ParseError> {
+        let script = self.rhai.compile_ast(script)?;
+        self.tools.insert(name.to_string(), script);
+        Ok(())
+    }
+
+    /// Runs the tool called `name` for `event`, with `view` centered on the
+    /// pointer. Unknown tools do nothing.
+    pub(crate) fn run_tool(&mut self, name: &str, event: &UserEvent, view: &WorldView) -> Result<(), Box<EvalAltResult>> {
+        let script = match self.tools.get(name) {
+            Some(script) => script,
+            None => return Ok(()),
+        };
+
+        let len = self.rhai.scope.len();
+        self.rhai.scope.push("pointer", tools::pointer(event));
+        self.rhai.scope.push("view", view.clone());
+        let side = 2 * event.size as u64 + 1;
+        let operations = MAX_TOOL_OPERATIONS_PER_CELL.saturating_mul(side.saturating_mul(side));
+        self.rhai.engine.set_max_operations(MAX_OPERATIONS.saturating_add(operations));
+        let result = self.rhai.engine.eval_ast_with_scope::<()>(&mut self.rhai.scope, script);
+        self.rhai.engine.set_max_operations(MAX_OPERATIONS);
+        self.rhai.scope.rewind(len);
+
+        result
+    }
+
+    pub(crate) fn set_hook(&mut self, kind: Kind, hook: Hook, script: &str) -> Result<(), ParseError> {
+        let script = self.rhai.compile_ast(script)?;
+        self.hooks.insert((kind, hook), script);
+        Ok(())
+    }
+
+    pub(crate) fn has_hook(&self, kind: Kind, hook: Hook) -> bool {
+        self.hooks.contains_key(&(kind, hook))
+    }
+
+    /// Runs the hook script for `event`, if there is one, with `view`
+    /// centered on the event's cell.
+    pub(crate) fn run_hook(&mut self, event: &Event, view: &WorldView) -> Result<(), Box<EvalAltResult>> {
+        let script = match self.hooks.get(&(event.kind(), event.hook())) {
+            Some(script) => script,
+            None => return Ok(()),
+        };
+
+        let state = self.states.entry(event.kind()).or_default().clone();
+        let len = self.rhai.scope.len();
+        self.rhai.scope.push("state", state);
+        self.rhai.scope.push("current", view.clone().get(0, 0));
+        self.rhai.scope.push("view", view.clone());
+        self.rhai.scope.push("event", event.to_map());
+        let result = self.rhai.engine.eval_ast_with_scope::<()>(&mut self.rhai.scope, script);
+        self.rhai.scope.rewind(len);
+
+        result
+    }
+
+    pub(crate) fn remove_script(&mut self, kind: Kind) {
+        self.scripts.remove(&kind);
+        self.foreign.remove(&kind);
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: u8) {
+        self.clock = clock;
+        self.rhai.scope.set_value("clock", clock as i32);
+    }
+
+    /// Runs the script for `current`'s material, if it has one, with `view`
+    /// centered on the cell.
+    pub(crate) fn run(&mut self, current: Particle, view: &WorldView) -> Result<(), Box<EvalAltResult>> {
+        let state = self.states.entry(current.kind).or_default().clone();
+        let mut host = Host::new(current, view.clone(), self.clock, self.rng.clone(), state, self.global.clone());
+        if let Some(script) = self.scripts.get(&current.kind) {
+            return self.rhai.run(script, &mut host);
+        }
+        match self.foreign.get(&current.kind) {
+            Some(run) => run(&mut host).map_err(Into::into),
+            None => Ok(()),
+        }
+    }
+
+    /// The top-level variables of `current`'s Rhai script once it has run on
+    /// `view`, see `RhaiBackend::locals`. The script really runs, so `view`
+    /// should be on a copy of the world; the rng and persistent state are put
+    /// back afterwards.
+    pub(crate) fn locals(&mut self, current: Particle, view: &WorldView) -> Vec<(String, String)> {
+        let script = match self.scripts.get(&current.kind) {
+            Some(script) => script,
+            None => return Vec::new(),
+        };
+        let state = self.states.entry(current.kind).or_default().clone();
+        let rng = self.rng.borrow_mut().clone();
+        let saved = (state.0.borrow().clone(), self.global.0.borrow().clone());
+
+        let mut host = Host::new(current, view.clone(), self.clock, self.rng.clone(), state.clone(), self.global.clone());
+        let locals = self.rhai.locals(script, &mut host);
+
+        *self.rng.borrow_mut() = rng;
+        *state.0.borrow_mut() = saved.0;
+        *self.global.0.borrow_mut() = saved.1;
+        locals
+    }
+
+    /// `clock` and the persistent state visible to scripts of `kind`.
+    pub(crate) fn variables(&self, kind: Kind) -> Vec<(String, String)> {
+        let mut variables = vec![("clock".to_string(), self.rhai.scope.get_value::<Dynamic>("clock").unwrap().to_string())];
+        let mut add = |prefix: &str, state: &ScriptState| {
+            let state = state.0.borrow();
+            let mut entries: Vec<_> = state.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                variables.push((format!("{}.{}", prefix, key), value.to_string()));
+            }
+        };
+        if let Some(state) = self.states.get(&kind) {
+            add("state", state);
+        }
+        add("global", &self.global);
+        variables
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut Writer) {
+        writer.map(&self.global.0.borrow());
+
+        let mut states: Vec<_> = self.states.iter()
+            .filter(|(_, state)| !state.0.borrow().is_empty())
+            .collect();
+        states.sort_by_key(|(kind, _)| kind.value());
+        writer.u32(states.len() as u32);
+        for (kind, state) in states {
+            writer.u8(kind.value() as u8);
+            writer.map(&state.0.borrow());
+        }
+    }
+
+    pub(crate) fn load_state(reader: &mut Reader) -> Result<(Map, Vec<(Kind, Map)>), SnapshotError> {
+        let global = reader.map()?;
+        let count = reader.length(5)?;
+        let mut states = Vec::with_capacity(count);
+        for _ in 0..count {
+            let kind = Kind::from_value(reader.u8()? as i32)
+                .ok_or_else(|| SnapshotError::Invalid("unknown material".to_string()))?;
+            states.push((kind, reader.map()?));
+        }
+        Ok((global, states))
+    }
+
+    /// Replaces all persistent state, keeping the handles scripts already hold.
+    pub(crate) fn restore_state(&mut self, (global, states): (Map, Vec<(Kind, Map)>)) {
+        *self.global.0.borrow_mut() = global;
+        for state in self.states.values() {
+            state.0.borrow_mut().clear();
+        }
+        for (kind, state) in states {
+            *self.states.entry(kind).or_default().0.borrow_mut() = state;
+        }
+    }
 }
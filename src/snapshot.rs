@@ -0,0 +1,241 @@
+use std::fmt;
+use std::convert::TryInto;
+use rhai::{Array, Dynamic, ImmutableString, Map, FLOAT, INT};
+
+const MAGIC: &[u8; 4] = b"SBOX";
+const VERSION: u8 = 1;
+
+// Deeper values than this are rejected rather than risking the stack.
+const MAX_DEPTH: usize = 32;
+
+const UNIT: u8 = 0;
+const BOOL: u8 = 1;
+const INTEGER: u8 = 2;
+const FLOATING: u8 = 3;
+const STRING: u8 = 4;
+const CHAR: u8 = 5;
+const ARRAY: u8 = 6;
+const MAP: u8 = 7;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a sandbox snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Invalid(message) => write!(f, "invalid snapshot: {}", message),
+        }
+    }
+}
+
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        Self { bytes }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn value(&mut self, value: &Dynamic) {
+        if let Ok(value) = value.as_bool() {
+            self.u8(BOOL);
+            self.u8(value as u8);
+        } else if let Ok(value) = value.as_int() {
+            self.u8(INTEGER);
+            self.i32(value);
+        } else if let Ok(value) = value.as_float() {
+            self.u8(FLOATING);
+            self.f64(value);
+        } else if let Ok(value) = value.as_str() {
+            self.u8(STRING);
+            self.string(value);
+        } else if let Ok(value) = value.as_char() {
+            self.u8(CHAR);
+            self.u32(value as u32);
+        } else if value.is::<Array>() {
+            let array = value.clone().cast::<Array>();
+            self.u8(ARRAY);
+            self.u32(array.len() as u32);
+            for item in &array {
+                self.value(item);
+            }
+        } else if value.is::<Map>() {
+            self.u8(MAP);
+            self.map(&value.clone().cast::<Map>());
+        } else {
+            // Host types such as particles can't be restored, so they're dropped.
+            self.u8(UNIT);
+        }
+    }
+
+    pub(crate) fn map(&mut self, map: &Map) {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        self.u32(entries.len() as u32);
+        for (key, value) in entries {
+            self.string(key);
+            self.value(value);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Result<Self, SnapshotError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let mut reader = Self { bytes, position: MAGIC.len() };
+        match reader.u8()? {
+            VERSION => Ok(reader),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SnapshotError> {
+        if length > self.remaining() {
+            return Err(SnapshotError::Truncated);
+        }
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn array<T>(&mut self, convert: impl FnOnce([u8; 4]) -> T) -> Result<T, SnapshotError> {
+        Ok(convert(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        self.array(u32::from_le_bytes)
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, SnapshotError> {
+        self.array(i32::from_le_bytes)
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, SnapshotError> {
+        self.array(f32::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Result<f64, SnapshotError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a length, checking that at least `length * size` bytes follow so
+    /// corrupt lengths fail before anything gets allocated.
+    pub(crate) fn length(&mut self, size: usize) -> Result<usize, SnapshotError> {
+        let length = self.u32()? as usize;
+        match length.checked_mul(size) {
+            Some(bytes) if bytes <= self.remaining() => Ok(length),
+            _ => Err(SnapshotError::Truncated),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let length = self.length(1)?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| SnapshotError::Invalid("string is not utf-8".to_string()))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Dynamic, SnapshotError> {
+        if depth > MAX_DEPTH {
+            return Err(SnapshotError::Invalid("values are nested too deeply".to_string()));
+        }
+        let value = match self.u8()? {
+            UNIT => Dynamic::from(()),
+            BOOL => Dynamic::from(self.u8()? != 0),
+            INTEGER => Dynamic::from(self.i32()? as INT),
+            FLOATING => Dynamic::from(self.f64()? as FLOAT),
+            STRING => Dynamic::from(ImmutableString::from(self.string()?)),
+            CHAR => match std::char::from_u32(self.u32()?) {
+                Some(value) => Dynamic::from(value),
+                None => return Err(SnapshotError::Invalid("bad char".to_string())),
+            },
+            ARRAY => {
+                let length = self.length(1)?;
+                let mut array = Array::with_capacity(length);
+                for _ in 0..length {
+                    array.push(self.value(depth + 1)?);
+                }
+                Dynamic::from(array)
+            }
+            MAP => Dynamic::from(self.map_at(depth + 1)?),
+            tag => return Err(SnapshotError::Invalid(format!("unknown value tag {}", tag))),
+        };
+        Ok(value)
+    }
+
+    fn map_at(&mut self, depth: usize) -> Result<Map, SnapshotError> {
+        let length = self.length(5)?;
+        let mut map = Map::new();
+        for _ in 0..length {
+            let key = self.string()?;
+            map.insert(key, self.value(depth)?);
+        }
+        Ok(map)
+    }
+
+    pub(crate) fn map(&mut self) -> Result<Map, SnapshotError> {
+        self.map_at(0)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), SnapshotError> {
+        if self.remaining() == 0 {
+            Ok(())
+        } else {
+            Err(SnapshotError::Invalid("trailing bytes".to_string()))
+        }
+    }
+}
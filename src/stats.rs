@@ -0,0 +1,104 @@
+use crate::engine::Kind;
+
+/// Milliseconds since an arbitrary point, for timing within a tick.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    use std::time::Instant;
+    thread_local! {
+        static START: Instant = Instant::now();
+    }
+    START.with(|start| start.elapsed().as_secs_f64() * 1000.0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now() -> f64 {
+    thread_local! {
+        static PERFORMANCE: Option<web_sys::Performance> =
+            web_sys::window().and_then(|window| window.performance());
+    }
+    PERFORMANCE.with(|performance| match performance {
+        Some(performance) => performance.now(),
+        None => js_sys::Date::now(),
+    })
+}
+
+/// Times updates by material, reading the clock only when the material
+/// changes and charging the time since to the one that was running. Timing
+/// each cell would read it twice per cell, and browsers coarsen it enough
+/// that most of those would come out as 0.
+#[derive(Default)]
+pub(crate) struct MaterialTimer {
+    running: Option<(Kind, f64)>,
+}
+
+impl MaterialTimer {
+    /// Call before updating a cell of `kind`.
+    pub(crate) fn start(&mut self, kind: Kind, stats: &mut TickStats) {
+        if let Some((running, _)) = self.running {
+            if running == kind {
+                return;
+            }
+        }
+        let now = now();
+        self.charge(now, stats);
+        self.running = Some((kind, now));
+    }
+
+    /// Charges the running material up to now, and stops timing it.
+    pub(crate) fn stop(&mut self, stats: &mut TickStats) {
+        if self.running.is_some() {
+            self.charge(now(), stats);
+            self.running = None;
+        }
+    }
+
+    fn charge(&self, now: f64, stats: &mut TickStats) {
+        if let Some((kind, since)) = self.running {
+            stats.materials[kind.value() as usize].time += now - since;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MaterialStats {
+    /// Cells of this material that were updated.
+    pub cells: u32,
+    /// Milliseconds spent in this material's behavior or script, see
+    /// `MaterialTimer`.
+    pub time: f64,
+}
+
+/// What the last `Sandbox::tick` spent its time on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickStats {
+    /// Milliseconds for the whole tick, including the brush.
+    pub time: f64,
+    materials: [MaterialStats; Kind::COUNT],
+}
+
+impl TickStats {
+    pub fn material(&self, kind: Kind) -> MaterialStats {
+        self.materials[kind.value() as usize]
+    }
+
+    pub(crate) fn count(&mut self, kind: Kind) {
+        self.materials[kind.value() as usize].cells += 1;
+    }
+
+    /// `{ time, materials: { sand: { cells, time }, ... } }`, for the browser.
+    pub fn to_js(&self) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::JsValue;
+        let materials = js_sys::Object::new();
+        for (index, stats) in self.materials.iter().enumerate() {
+            let kind = Kind::from_value(index as i32).unwrap();
+            let entry = js_sys::Object::new();
+            js_sys::Reflect::set(&entry, &"cells".into(), &JsValue::from(stats.cells)).unwrap();
+            js_sys::Reflect::set(&entry, &"time".into(), &JsValue::from(stats.time)).unwrap();
+            js_sys::Reflect::set(&materials, &kind.name().into(), &entry).unwrap();
+        }
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"time".into(), &JsValue::from(self.time)).unwrap();
+        js_sys::Reflect::set(&object, &"materials".into(), &materials).unwrap();
+        object.into()
+    }
+}
@@ -0,0 +1,151 @@
+//! Tools: scripts run once per tick while the pointer is down, in place of
+//! the default square brush.
+//!
+//! Tools see `view`, centered on the pointer, and `pointer`, a map with
+//! `x`, `y`, `previous_x` and `previous_y` (the position on the last tick,
+//! or the current one if the pointer just went down), `dx` and `dy` (the
+//! movement since then), `size`, `kind` (the selected material) and the
+//! `shift`, `ctrl` and `alt` modifiers. `particle(kind)` makes a new particle
+//! for `view.set`.
+
+use rhai::{Dynamic, Engine, Map, RegisterResultFn};
+use crate::engine::{Kind, Particle, UserEvent};
+
+/// Scatters the selected material over empty cells.
+pub static SPRAY: &str = r"
+    let radius = pointer.size;
+    for d_y in range(-radius, radius + 1) {
+        for d_x in range(-radius, radius + 1) {
+            if d_x * d_x + d_y * d_y <= radius * radius && view.kind(d_x, d_y) == KIND_EMPTY && rng.gen_bool(0.05) {
+                view.set(d_x, d_y, particle(pointer.kind));
+            }
+        }
+    }
+";
+
+/// Drags particles along with the pointer.
+pub static SMUDGE: &str = r"
+    let radius = pointer.size / 2 + 1;
+    if pointer.dx != 0 || pointer.dy != 0 {
+        for o_y in range(-radius, radius + 1) {
+            for o_x in range(-radius, radius + 1) {
+                let from = view.get(o_x - pointer.dx, o_y - pointer.dy);
+                let kind = from.kind;
+                if kind != KIND_EMPTY && kind != KIND_OUT_OF_BOUNDS && view.kind(o_x, o_y) == KIND_EMPTY {
+                    view.set(o_x, o_y, from);
+                    view.set(o_x - pointer.dx, o_y - pointer.dy, EMPTY);
+                }
+            }
+        }
+    }
+";
+
+/// Sets plants on fire and boils water away.
+pub static HEAT: &str = r"
+    let radius = pointer.size;
+    for d_y in range(-radius, radius + 1) {
+        for d_x in range(-radius, radius + 1) {
+            if d_x * d_x + d_y * d_y <= radius * radius {
+                let kind = view.kind(d_x, d_y);
+                if kind == KIND_PLANT && rng.gen_bool(0.2) {
+                    view.set(d_x, d_y, particle(KIND_FIRE));
+                } else if kind == KIND_WATER && rng.gen_bool(0.1) {
+                    view.set(d_x, d_y, EMPTY);
+                }
+            }
+        }
+    }
+";
+
+/// Removes everything, or with shift only the selected material.
+pub static VACUUM: &str = r"
+    let radius = pointer.size;
+    for d_y in range(-radius, radius + 1) {
+        for d_x in range(-radius, radius + 1) {
+            let kind = view.kind(d_x, d_y);
+            if d_x * d_x + d_y * d_y <= radius * radius && kind != KIND_EMPTY
+                    && (pointer.shift == false || kind == pointer.kind) {
+                view.set(d_x, d_y, EMPTY);
+            }
+        }
+    }
+";
+
+/// Turns particles into the selected material, or with shift only those of
+/// the material under the pointer.
+pub static REPLACE: &str = r"
+    let radius = pointer.size;
+    let target = view.kind(0, 0);
+    for d_y in range(-radius, radius + 1) {
+        for d_x in range(-radius, radius + 1) {
+            let kind = view.kind(d_x, d_y);
+            if kind != KIND_EMPTY && kind != KIND_OUT_OF_BOUNDS && kind != pointer.kind
+                    && (pointer.shift == false || kind == target) {
+                view.set(d_x, d_y, particle(pointer.kind));
+            }
+        }
+    }
+";
+
+/// The built-in tools, registered with every sandbox.
+pub static TOOLS: [(&str, &str); 5] = [
+    ("spray", SPRAY),
+    ("smudge", SMUDGE),
+    ("heat", HEAT),
+    ("vacuum", VACUUM),
+    ("replace", REPLACE),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushShape {
+    Square,
+    Circle,
+}
+
+/// The shape and radius a brush of `size` covers with `tool`, or with the
+/// plain brush for `None`. Tools that aren't built in are taken to cover a
+/// circle, like most built-in ones.
+pub fn footprint(tool: Option<&str>, size: u32) -> (BrushShape, i32) {
+    let size = size as i32;
+    match tool {
+        None | Some("replace") => (BrushShape::Square, size),
+        Some("smudge") => (BrushShape::Square, size / 2 + 1),
+        Some(_) => (BrushShape::Circle, size),
+    }
+}
+
+/// Keys held while using a tool.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+/// The `pointer` map tools see for `event`.
+pub(crate) fn pointer(event: &UserEvent) -> Map {
+    let (previous_x, previous_y) = event.previous.unwrap_or((event.x, event.y));
+    let mut pointer = Map::new();
+    let mut insert = |key: &str, value: Dynamic| {
+        pointer.insert(key.to_string(), value);
+    };
+    insert("x", Dynamic::from(event.x));
+    insert("y", Dynamic::from(event.y));
+    insert("previous_x", Dynamic::from(previous_x));
+    insert("previous_y", Dynamic::from(previous_y));
+    insert("dx", Dynamic::from(event.x - previous_x));
+    insert("dy", Dynamic::from(event.y - previous_y));
+    insert("size", Dynamic::from(event.size as i32));
+    insert("kind", Dynamic::from(event.kind.value()));
+    insert("shift", Dynamic::from(event.modifiers.shift));
+    insert("ctrl", Dynamic::from(event.modifiers.ctrl));
+    insert("alt", Dynamic::from(event.modifiers.alt));
+    pointer
+}
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_result_fn("particle", |kind: i32| match Kind::from_value(kind) {
+        Some(Kind::OutOfBounds) | None => Err(format!("unknown material {}", kind).into()),
+        Some(kind) => Ok(Dynamic::from(Particle::new(kind))),
+    });
+}
@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rhai::Dynamic;
+use sandbox::backend::{Host, ScriptBackend};
+use sandbox::compiler::RuleBackend;
+use sandbox::engine::{Kind, Sandbox, UserEvent, EMPTY};
+
+/// Scripts are lists of moves, like `down left`. A particle takes the first
+/// move into an empty cell.
+#[derive(Default)]
+struct Moves {
+    runs: u32,
+}
+
+impl ScriptBackend for Moves {
+    type Script = Vec<(i32, i32)>;
+    type Error = String;
+
+    fn compile(&mut self, source: &str) -> Result<Vec<(i32, i32)>, String> {
+        source.split_whitespace().map(|word| match word {
+            "down" => Ok((0, 1)),
+            "left" => Ok((-1, 0)),
+            "right" => Ok((1, 0)),
+            _ => Err(format!("unknown move `{}`", word)),
+        }).collect()
+    }
+
+    fn run(&mut self, script: &Vec<(i32, i32)>, host: &mut Host) -> Result<(), String> {
+        self.runs += 1;
+        let runs = host.global("runs").map_or(0, |runs| runs.as_int().unwrap());
+        host.set_global("runs", Dynamic::from(runs + 1));
+        let current = host.current();
+        for &(d_x, d_y) in script {
+            if host.view().get(d_x, d_y).kind == Kind::Empty {
+                host.view().set(d_x, d_y, current);
+                host.view().set(0, 0, EMPTY);
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn runs_scripts_through_custom_backends() {
+    let backend = Rc::new(RefCell::new(Moves::default()));
+    let mut sandbox = Sandbox::new(4, 3);
+    assert_eq!(sandbox.register_script_with(Kind::Plant, backend.clone(), "down jump"),
+               Err("unknown move `jump`".to_string()));
+    sandbox.register_script_with(Kind::Plant, backend.clone(), "down").unwrap();
+    sandbox.register_script(Kind::Fire, r#"
+        if global.get("runs", 0) == 3 {
+            view.set(0, 0, EMPTY);
+        }
+    "#).unwrap();
+
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Plant, 0)));
+    sandbox.tick(None);
+    sandbox.tick(None);
+    assert_eq!(sandbox.to_ascii(), "....\n....\n.P..");
+    assert_eq!(backend.borrow().runs, 2);
+
+    // Rhai scripts see what other backends store.
+    sandbox.tick(Some(UserEvent::new(0, 0, Kind::Fire, 0)));
+    sandbox.tick(None);
+    assert_eq!(sandbox.to_ascii(), "....\n....\n.P..");
+
+    sandbox.register_script(Kind::Plant, "view.set(0, 0, EMPTY);").unwrap();
+    sandbox.tick(None);
+    assert_eq!(backend.borrow().runs, 4);
+    assert_eq!(sandbox.to_ascii(), "....\n....\n....");
+}
+
+#[test]
+fn runs_only_restricted_rules_through_the_rule_backend() {
+    let backend = Rc::new(RefCell::new(RuleBackend));
+    let mut sandbox = Sandbox::new(3, 3);
+    let error = sandbox.register_script_with(Kind::Plant, backend.clone(), "let x = 1;").unwrap_err();
+    assert_eq!(error.line, 1);
+
+    sandbox.register_script_with(Kind::Plant, backend, r"
+        if view.kind(0, 1) == KIND_EMPTY {
+            view.swap(0, 1);
+        }
+    ").unwrap();
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Plant, 0)));
+    sandbox.tick(None);
+    sandbox.tick(None);
+    assert_eq!(sandbox.to_ascii(), "...\n...\n.P.");
+}
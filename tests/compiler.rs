@@ -0,0 +1,187 @@
+use std::mem::size_of;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use sandbox::compiler::Rule;
+use sandbox::engine::{Kind, Particle, Sandbox, ScriptRunner, UserEvent};
+use wasmi::memory_units::Pages;
+use wasmi::nan_preserving_float::F64;
+use wasmi::{Externals, FuncInstance, FuncRef, ImportsBuilder, MemoryDescriptor, MemoryInstance, MemoryRef,
+            ModuleImportResolver, ModuleInstance, NopExternals, RuntimeArgs, RuntimeValue, Signature, Trap};
+
+static FALL: &str = r"
+    // Fall straight down, or sometimes to the side.
+    if view.kind(0, 1) == KIND_EMPTY {
+        view.swap(0, 1);
+    } else if rng.gen_bool(0.5) && view.kind(-1, 1) != KIND_SAND {
+        view.set(-1, 1, current);
+        view.set(0, 0, EMPTY);
+    }
+";
+
+#[test]
+fn compiles_restricted_rules() {
+    let rule = Rule::parse(FALL).unwrap();
+    let wasm = rule.to_wasm();
+    let module = walrus::Module::from_buffer(&wasm).unwrap();
+    assert!(module.exports.iter().any(|export| export.name == "update"));
+    assert!(module.exports.iter().any(|export| export.name == "configure"));
+
+    let mut sandbox = Sandbox::new(10, 10);
+    assert_eq!(sandbox.register_script(Kind::Plant, FALL), Ok(ScriptRunner::Rule));
+}
+
+static TUMBLE: &str = r"
+    if view.kind(0, 1) == KIND_EMPTY {
+        view.swap(0, 1);
+    } else if rng.gen_bool(0.5) && view.kind(-1, 1) != KIND_SAND {
+        view.set(-1, 1, current);
+        view.set(0, 0, EMPTY);
+    } else if !(view.kind(1, 1) == KIND_SAND || rng.gen_bool(0.3)) {
+        view.swap(1, 1);
+    }
+";
+
+static TERRAIN: &str = "
+    ........
+    ..S.....
+    .S..S...
+    ........
+    S.S..SS.
+    SSS.SSSS
+";
+
+/// Gives compiled modules their world and draws their random branches from
+/// `rng`, like the sandbox does.
+struct Env(MemoryRef);
+
+impl ModuleImportResolver for Env {
+    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, wasmi::Error> {
+        assert_eq!(field_name, "gen_bool");
+        Ok(FuncInstance::alloc_host(signature.clone(), 0))
+    }
+
+    fn resolve_memory(&self, field_name: &str, _: &MemoryDescriptor) -> Result<MemoryRef, wasmi::Error> {
+        assert_eq!(field_name, "memory");
+        Ok(self.0.clone())
+    }
+}
+
+struct Rand<'a>(&'a mut StdRng);
+
+impl Externals for Rand<'_> {
+    fn invoke_index(&mut self, _: usize, args: RuntimeArgs) -> Result<Option<RuntimeValue>, Trap> {
+        let probability: F64 = args.nth_checked(0)?;
+        Ok(Some(RuntimeValue::I32(self.0.gen_bool(probability.to_float()) as i32)))
+    }
+}
+
+fn read_particles(memory: &MemoryRef, count: usize) -> Vec<Particle> {
+    memory.with_direct_access(|bytes| (0..count)
+        .map(|index| unsafe {
+            std::ptr::read_unaligned(bytes[index * size_of::<Particle>()..].as_ptr() as *const Particle)
+        })
+        .collect())
+}
+
+fn write_particles(memory: &MemoryRef, particles: &[Particle]) {
+    memory.with_direct_access_mut(|bytes| {
+        for (index, particle) in particles.iter().enumerate() {
+            unsafe {
+                std::ptr::write_unaligned(bytes[index * size_of::<Particle>()..].as_mut_ptr() as *mut Particle,
+                                          *particle);
+            }
+        }
+    });
+}
+
+#[test]
+fn runs_compiled_modules_like_the_interpreters() {
+    let module = wasmi::Module::from_buffer(Rule::parse(TUMBLE).unwrap().to_wasm()).unwrap();
+    for seed in 0..8 {
+        for &x in &[0, 3, 7] {
+            let mut terrain = Sandbox::from_ascii(TERRAIN).unwrap();
+            terrain.register_script(Kind::Sand, "").unwrap();
+            terrain.register_script(Kind::Plant, TUMBLE).unwrap();
+            terrain.tick(Some(UserEvent::new(x, 0, Kind::Plant, 0)));
+            let (width, height) = (terrain.width(), terrain.height());
+
+            // Compiled natively and interpreted by Rhai, which the debugger
+            // uses for every script.
+            let mut compiled = Sandbox::new(width, height);
+            let mut interpreted = Sandbox::new(width, height);
+            interpreted.set_debugging(true);
+            for sandbox in &mut [&mut compiled, &mut interpreted] {
+                sandbox.restore(&terrain.snapshot()).unwrap();
+                sandbox.register_script(Kind::Sand, "").unwrap();
+                sandbox.register_script(Kind::Plant, TUMBLE).unwrap();
+                sandbox.seed(seed);
+            }
+
+            let memory = MemoryInstance::alloc(Pages(1), None).unwrap();
+            let env = Env(memory.clone());
+            let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+                .unwrap()
+                .assert_no_start();
+            write_particles(&memory, &terrain.particles());
+            let configure = [RuntimeValue::I32(0), RuntimeValue::I32(width), RuntimeValue::I32(height)];
+            instance.invoke_export("configure", &configure, &mut NopExternals).unwrap();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let count = (width * height) as usize;
+
+            for tick in 0..12 {
+                compiled.tick(None);
+                interpreted.tick(None);
+
+                // The plant is the only particle that moves, so it updates once.
+                let plant = read_particles(&memory, count).iter().position(|particle| particle.kind == Kind::Plant);
+                if let Some(index) = plant {
+                    let args = [
+                        RuntimeValue::I32(index as i32 % width),
+                        RuntimeValue::I32(index as i32 / width),
+                        RuntimeValue::I32(compiled.clock() as i32),
+                    ];
+                    instance.invoke_export("update", &args, &mut Rand(&mut rng)).unwrap();
+                }
+                let particles = read_particles(&memory, count);
+                assert_eq!(&*compiled.particles(), &particles[..], "seed {}, x {}, tick {}", seed, x, tick);
+                assert_eq!(&*interpreted.particles(), &particles[..], "seed {}, x {}, tick {}", seed, x, tick);
+            }
+        }
+    }
+}
+
+#[test]
+fn stores_non_square_worlds_row_by_row() {
+    let world = "S....\n.....\n....W";
+    let sandbox = Sandbox::from_ascii(world).unwrap();
+    assert_eq!(sandbox.get(4, 2).kind, Kind::Water);
+    assert_eq!(sandbox.particles()[2 * 5 + 4].kind, Kind::Water);
+    assert_eq!(sandbox.to_ascii(), world);
+}
+
+#[test]
+fn rejects_unsupported_constructs() {
+    let error = Rule::parse("let dx = 1;\nview.swap(dx, 1);").unwrap_err();
+    assert_eq!(error.line, 1);
+    assert!(Rule::parse("view.set(0, 1, KIND_SAND);").is_err());
+    assert!(Rule::parse("if view.kind(0, 1) == KIND_EMPTY { view.swap(0, 1);").is_err());
+}
+
+#[test]
+fn falls_back_to_rhai() {
+    let mut sandbox = Sandbox::new(10, 10);
+    let runner = sandbox.register_script(Kind::Plant, r"
+        let dy = 1;
+        if view.kind(0, dy) == KIND_EMPTY {
+            view.swap(0, dy);
+        }
+    ");
+    assert_eq!(runner, Ok(ScriptRunner::Rhai));
+    sandbox.tick(Some(UserEvent::new(5, 0, Kind::Plant, 0)));
+    for _ in 0..20 {
+        sandbox.tick(None);
+    }
+    assert!(sandbox.errors().is_empty());
+    assert_eq!(sandbox.get(5, 0).kind, Kind::Empty);
+    assert_eq!(sandbox.get(5, 9).kind, Kind::Plant);
+}
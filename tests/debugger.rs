@@ -0,0 +1,127 @@
+use sandbox::debugger::{Breakpoint, SetCall};
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+
+// Plants grow into the empty cell below them.
+static GROW: &str = r#"
+    let below = view.kind(0, 1);
+    state.set("grown", state.get("grown", 0) + 1);
+    if below == KIND_EMPTY {
+        view.set(0, 1, current);
+    }
+"#;
+
+fn sandbox() -> Sandbox {
+    let mut sandbox = Sandbox::new(3, 4);
+    sandbox.register_script(Kind::Plant, GROW).unwrap();
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Plant, 0)));
+    sandbox.set_debugging(true);
+    sandbox
+}
+
+#[test]
+fn breaks_before_cells_update() {
+    let mut sandbox = sandbox();
+    sandbox.debugger_mut().add_breakpoint(Breakpoint::Cell(1, 0));
+    sandbox.tick(None);
+
+    let pause = sandbox.debugger().paused().unwrap().clone();
+    assert_eq!((pause.x, pause.y), (1, 0));
+    assert_eq!(pause.neighborhood[1][1].kind, Kind::Plant);
+    assert_eq!(pause.neighborhood[0][1].kind, Kind::OutOfBounds);
+    assert_eq!(pause.neighborhood[2][1].kind, Kind::Empty);
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Empty);
+
+    // Ticks wait for the debugger.
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Empty);
+
+    // Stepping past the last update finishes the tick, stepping again
+    // starts the next one.
+    sandbox.step();
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Plant);
+    assert!(sandbox.debugger().paused().is_none());
+    sandbox.step();
+    let pause = sandbox.debugger().paused().unwrap();
+    assert_eq!((pause.x, pause.y), (1, 0));
+    assert!(pause.variables.contains(&("state.grown".to_string(), "1".to_string())));
+
+    sandbox.resume();
+    assert!(sandbox.debugger().paused().is_none());
+    assert_eq!(sandbox.get(1, 2).kind, Kind::Plant);
+
+    sandbox.debugger_mut().clear_breakpoints();
+    sandbox.debugger_mut().add_breakpoint(Breakpoint::Material(Kind::Plant));
+    sandbox.tick(None);
+    assert!(sandbox.debugger().paused().is_some());
+}
+
+#[test]
+fn shows_the_paused_scripts_locals() {
+    let mut sandbox = sandbox();
+    sandbox.debugger_mut().add_breakpoint(Breakpoint::Cell(1, 0));
+    sandbox.tick(None);
+
+    // `below` is `KIND_EMPTY`. Working it out doesn't update the cell, or
+    // count towards `state.grown`.
+    let pause = sandbox.debugger().paused().unwrap().clone();
+    assert_eq!(pause.locals, vec![("below".to_string(), "4".to_string())]);
+    assert!(pause.variables.iter().all(|(name, _)| name != "state.grown"));
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Empty);
+    assert!(sandbox.trace().is_empty());
+
+    sandbox.resume();
+    sandbox.tick(None);
+    let pause = sandbox.debugger().paused().unwrap();
+    assert_eq!(pause.locals, vec![("below".to_string(), "1".to_string())]);
+    assert!(pause.variables.contains(&("state.grown".to_string(), "1".to_string())));
+}
+
+#[test]
+fn traces_writes_with_lines() {
+    let mut sandbox = sandbox();
+    sandbox.tick(None);
+    assert_eq!(sandbox.trace(), vec![SetCall { from: (1, 0), x: 1, y: 1, kind: Kind::Plant, line: Some(5) }]);
+
+    // Compiled rules run through Rhai while debugging.
+    sandbox.tick(Some(UserEvent::new(0, 0, Kind::Sand, 0)));
+    sandbox.tick(None);
+    let swaps: Vec<_> = sandbox.trace().into_iter().filter(|call| call.from == (0, 0)).collect();
+    assert_eq!(swaps.len(), 2);
+    assert!(swaps.iter().all(|call| call.line == Some(3)));
+
+    sandbox.set_debugging(false);
+    sandbox.tick(None);
+    assert!(sandbox.trace().is_empty());
+}
+
+#[test]
+fn finishes_the_paused_tick_when_debugging_stops() {
+    let mut sandbox = sandbox();
+    sandbox.debugger_mut().add_breakpoint(Breakpoint::Cell(1, 0));
+    sandbox.tick(None);
+    assert!(sandbox.debugger().paused().is_some());
+
+    sandbox.set_debugging(false);
+    assert!(sandbox.debugger().paused().is_none());
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Plant);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 2).kind, Kind::Plant);
+}
+
+#[test]
+fn traces_writes_it_cannot_rewrite_without_lines() {
+    let mut sandbox = Sandbox::new(3, 4);
+    sandbox.register_script(Kind::Plant, r"
+        let v = view;
+        v.set(0, 1, current);
+        view .set (1, 0, current);
+    ").unwrap();
+    sandbox.tick(Some(UserEvent::new(0, 0, Kind::Plant, 0)));
+    sandbox.set_debugging(true);
+    sandbox.tick(None);
+
+    let lines: Vec<_> = sandbox.trace().iter().map(|call| ((call.x, call.y), call.line)).collect();
+    assert_eq!(lines, vec![((0, 1), None), ((1, 0), None)]);
+    assert_eq!(sandbox.get(0, 1).kind, Kind::Plant);
+    assert_eq!(sandbox.get(1, 0).kind, Kind::Plant);
+}
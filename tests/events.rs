@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rand::Rng;
+use sandbox::engine::{Behavior, Kind, Sandbox, UserEvent, WorldView};
+use sandbox::events::{Event, Hook, MAX_EVENTS};
+
+#[test]
+fn runs_hook_scripts_for_placed_particles() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_hook(Kind::Plant, Hook::Place, r#"
+        global.set("placed", global.get("placed", 0) + 1);
+    "#).unwrap();
+    sandbox.register_script(Kind::Plant, r#"
+        if global.get("placed", 0) == 9 {
+            view.set(0, 0, EMPTY);
+        }
+    "#).unwrap();
+
+    sandbox.tick(Some(UserEvent::new(2, 2, Kind::Plant, 1)));
+    assert_eq!(sandbox.get(2, 2).kind, Kind::Plant);
+    sandbox.tick(None);
+    assert_eq!(sandbox.to_ascii(), ".....\n.....\n.....\n.....\n.....");
+}
+
+#[test]
+fn spreads_through_contacts_within_a_tick() {
+    let mut sandbox = Sandbox::new(6, 3);
+    sandbox.generate("world.fill(0, 1, 5, 1, KIND_PLANT);", 0).unwrap();
+    sandbox.register_hook(Kind::Plant, Hook::Contact, r"
+        if event.other == KIND_FIRE {
+            view.set(0, 0, particle(KIND_FIRE));
+        }
+    ").unwrap();
+
+    sandbox.tick(Some(UserEvent::new(0, 0, Kind::Fire, 0)));
+    assert_eq!(sandbox.to_ascii(), "F.....\nFFFFFF\n......");
+}
+
+#[test]
+fn ignores_swaps_and_caps_events_per_tick() {
+    // Each placed sand particle marks the next cell of the first column.
+    let mut sandbox = Sandbox::new(3, 4);
+    sandbox.register_hook(Kind::Sand, Hook::Place, r#"
+        let placed = global.get("placed", 0);
+        global.set("placed", placed + 1);
+        view.set(0 - event.x, placed - event.y, particle(KIND_PLANT));
+    "#).unwrap();
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Sand, 0)));
+    sandbox.tick(None);
+    sandbox.tick(None);
+    assert_eq!(sandbox.to_ascii(), "P..\n...\n.S.\n...");
+
+    // Plants and fire keep turning into each other. The brush's plant is
+    // the first event, so after an even number of them the last write is a
+    // plant, and the event it raised is dropped with the rest of the tick's.
+    sandbox.register_hook(Kind::Plant, Hook::Place, "view.set(0, 0, particle(KIND_FIRE));").unwrap();
+    sandbox.register_hook(Kind::Fire, Hook::Place, "view.set(0, 0, particle(KIND_PLANT));").unwrap();
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Plant, 0)));
+    assert_eq!(MAX_EVENTS % 2, 0);
+    assert_eq!(sandbox.get(1, 0).kind, Kind::Plant);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 0).kind, Kind::Plant);
+}
+
+struct Witness(Rc<RefCell<Vec<Event>>>);
+
+impl<R: Rng> Behavior<R> for Witness {
+    fn update(&self, _view: &mut WorldView, _rng: &mut R) {}
+
+    fn hooks(&self) -> &[Hook] {
+        &[Hook::Destroy, Hook::Contact]
+    }
+
+    fn on_event(&self, event: &Event, _view: &mut WorldView, _rng: &mut R) {
+        self.0.borrow_mut().push(*event);
+    }
+}
+
+#[test]
+fn delivers_events_to_behaviors() {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut sandbox = Sandbox::new(4, 3);
+    sandbox.register_behavior(Kind::Plant, Witness(events.clone()));
+    sandbox.tick(Some(UserEvent::new(1, 1, Kind::Plant, 0)));
+    assert!(events.borrow().is_empty());
+
+    sandbox.tick(Some(UserEvent::new(2, 1, Kind::Water, 0)));
+    assert_eq!(*events.borrow(), vec![
+        Event::Contact { x: 1, y: 1, kind: Kind::Plant, d_x: 1, d_y: 0, other: Kind::Water },
+    ]);
+
+    events.borrow_mut().clear();
+    sandbox.tick(Some(UserEvent::new(1, 1, Kind::Empty, 0)));
+    assert_eq!(*events.borrow(), vec![
+        Event::Destroy { x: 1, y: 1, kind: Kind::Plant, replaced_by: Kind::Empty },
+    ]);
+}
+
+#[test]
+fn water_moves_without_placing_or_destroying() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_hook(Kind::Water, Hook::Place, r#"throw "placed";"#).unwrap();
+    sandbox.register_hook(Kind::Water, Hook::Destroy, r#"throw "destroyed";"#).unwrap();
+    sandbox.tick(Some(UserEvent::new(2, 0, Kind::Water, 0)));
+    assert_eq!(sandbox.errors().len(), 1);
+    for _ in 0..6 {
+        sandbox.tick(None);
+        assert!(sandbox.errors().is_empty(), "{:?}", sandbox.errors());
+    }
+    assert_eq!(sandbox.get(2, 0).kind, Kind::Empty);
+    assert!((0..5).any(|x| sandbox.get(x, 4).kind == Kind::Water));
+}
@@ -0,0 +1,97 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::generators::GENERATORS;
+use sandbox::noise::Noise;
+
+fn kinds(sandbox: &Sandbox, width: i32, height: i32) -> Vec<Kind> {
+    (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| sandbox.get(x, y).kind)
+        .collect()
+}
+
+#[test]
+fn draws_with_fill_and_line() {
+    let mut sandbox = Sandbox::new(5, 4);
+    sandbox.generate(r"
+        world.fill(0, world.height - 1, 100, 100, KIND_SAND);
+        world.line(0, 0, 3, 3, KIND_PLANT);
+        world.set(4, 0, world.kind(0, 0));
+    ", 0).unwrap();
+
+    let rows: Vec<String> = (0..4)
+        .map(|y| (0..5).map(|x| sandbox.get(x, y).kind.to_char()).collect())
+        .collect();
+    assert_eq!(rows, vec!["P...P", ".P...", "..P..", "SSSPS"]);
+}
+
+#[test]
+fn clips_lines_to_the_world() {
+    let mut sandbox = Sandbox::new(5, 4);
+    sandbox.generate(r"
+        let max = 2147483647;
+        world.line(0 - max - 1, 0, max, 0, KIND_SAND);
+        world.line(2, 0 - max - 1, 2, max, KIND_PLANT);
+        world.line(0 - max - 1, 0 - max - 1, max, 0 - max - 1, KIND_WATER);
+        world.line(-3, 6, 6, -3, KIND_FIRE);
+    ", 0).unwrap();
+
+    let rows: Vec<String> = (0..4)
+        .map(|y| (0..5).map(|x| sandbox.get(x, y).kind.to_char()).collect())
+        .collect();
+    assert_eq!(rows, vec!["SSPFS", "..F..", ".FP..", "F.P.."]);
+}
+
+#[test]
+fn generates_the_same_world_from_the_same_seed() {
+    for (name, script) in GENERATORS.iter() {
+        let mut first = Sandbox::new(60, 40);
+        let mut second = Sandbox::new(60, 40);
+        first.generate(script, 7).unwrap();
+        second.generate(script, 7).unwrap();
+
+        let generated = kinds(&first, 60, 40);
+        assert_eq!(generated, kinds(&second, 60, 40), "{}", name);
+        assert!(generated.iter().any(|kind| *kind != Kind::Empty), "{}", name);
+    }
+}
+
+#[test]
+fn keeps_the_world_when_generators_fail() {
+    let mut sandbox = Sandbox::new(5, 4);
+    sandbox.generate("world.fill(0, 0, 4, 3, KIND_WATER);", 0).unwrap();
+    assert!(sandbox.generate("world.fill(0, 0, 4, 3, KIND_SAND); oops();", 0).is_err());
+    assert!(kinds(&sandbox, 5, 4).iter().all(|kind| *kind == Kind::Water));
+
+    // Nor does the random number generator, so ticks after a failed
+    // generator match ticks without one.
+    let mut failed = Sandbox::new(5, 4);
+    failed.seed(3);
+    assert!(failed.generate("rng.gen_bool(0.5); oops();", 9).is_err());
+    let mut untouched = Sandbox::new(5, 4);
+    untouched.seed(3);
+    for sandbox in &mut [&mut failed, &mut untouched] {
+        sandbox.tick(Some(UserEvent::new(2, 0, Kind::Sand, 1)));
+        for _ in 0..4 {
+            sandbox.tick(None);
+        }
+    }
+    assert_eq!(kinds(&failed, 5, 4), kinds(&untouched, 5, 4));
+}
+
+#[test]
+fn noise_stays_in_range() {
+    let noise = Noise::new(42);
+    for i in 0..2000 {
+        let (x, y) = (i as f64 * 0.37 - 300.0, i as f64 * 0.11);
+        for value in &[noise.value(x, y), noise.perlin(x, y), noise.simplex(x, y)] {
+            assert!(*value >= -1.0 && *value <= 1.0);
+        }
+    }
+    for &(x, y) in &[(1e12, 0.5), (-1e12, 0.5), (0.5, 1e12), (f64::MAX, f64::MIN)] {
+        for value in &[noise.value(x, y), noise.perlin(x, y), noise.simplex(x, y)] {
+            assert!(*value >= -1.0 && *value <= 1.0);
+        }
+    }
+    assert_eq!(noise.perlin(1.5, 2.5), Noise::new(42).perlin(1.5, 2.5));
+    let other = Noise::new(43);
+    assert!((0..20).any(|x| noise.simplex(x as f64 + 0.5, 2.5) != other.simplex(x as f64 + 0.5, 2.5)));
+}
@@ -0,0 +1,117 @@
+//! Runs every world in `tests/golden` and compares the result to the layout
+//! stored with it. Each file looks like:
+//!
+//! ```text
+//! # What the world shows.
+//! ticks: 10
+//! seed: 1
+//!
+//! before:
+//! ..S..
+//! .....
+//!
+//! after:
+//! .....
+//! ..S..
+//! ```
+//!
+//! Run with `BLESS=1` to write the current results as the new expectations.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use sandbox::engine::Sandbox;
+
+struct Golden {
+    header: Vec<String>,
+    ticks: u32,
+    seed: u64,
+    before: String,
+    after: Option<String>,
+}
+
+fn parse(source: &str) -> Result<Golden, String> {
+    let mut golden = Golden { header: Vec::new(), ticks: 0, seed: 0, before: String::new(), after: None };
+    let mut section = None;
+    for line in source.lines() {
+        let line = line.trim();
+        match (line, section) {
+            ("before:", None) => section = Some(false),
+            ("after:", Some(false)) => section = Some(true),
+            (_, Some(after)) => {
+                let rows = if after { golden.after.get_or_insert_with(String::new) } else { &mut golden.before };
+                if !line.is_empty() {
+                    rows.push_str(line);
+                    rows.push('\n');
+                }
+            }
+            (_, None) => {
+                golden.header.push(line.to_string());
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (key, value) = match line.find(':') {
+                    Some(colon) => (&line[..colon], line[colon + 1..].trim()),
+                    None => return Err(format!("expected `key: value`, got `{}`", line)),
+                };
+                match key {
+                    "ticks" => golden.ticks = value.parse().map_err(|_| format!("bad tick count `{}`", value))?,
+                    "seed" => golden.seed = value.parse().map_err(|_| format!("bad seed `{}`", value))?,
+                    _ => return Err(format!("unknown key `{}`", key)),
+                }
+            }
+        }
+    }
+    if section.is_none() {
+        return Err("missing `before:` section".to_string());
+    }
+    Ok(golden)
+}
+
+fn run(golden: &Golden) -> Result<String, String> {
+    let mut sandbox = Sandbox::from_ascii(&golden.before)?;
+    sandbox.seed(golden.seed);
+    for _ in 0..golden.ticks {
+        sandbox.tick(None);
+    }
+    Ok(sandbox.to_ascii() + "\n")
+}
+
+fn bless(path: &Path, golden: &Golden, actual: &str) {
+    let mut source = golden.header.join("\n");
+    source.push_str("\nbefore:\n");
+    source.push_str(&golden.before);
+    source.push_str("\nafter:\n");
+    source.push_str(actual);
+    fs::write(path, source).unwrap();
+}
+
+fn check(path: &Path, blessing: bool) -> Result<(), String> {
+    let golden = parse(&fs::read_to_string(path).map_err(|error| error.to_string())?)?;
+    let actual = run(&golden)?;
+    match &golden.after {
+        Some(expected) if *expected == actual => Ok(()),
+        _ if blessing => {
+            bless(path, &golden, &actual);
+            Ok(())
+        }
+        Some(expected) => Err(format!("expected:\n{}got:\n{}", expected, actual)),
+        None => Err("missing `after:` section, run with BLESS=1 to add it".to_string()),
+    }
+}
+
+#[test]
+fn golden_worlds() {
+    let blessing = std::env::var_os("BLESS").is_some();
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let failures: Vec<String> = paths.iter()
+        .filter_map(|path| check(path, blessing).err().map(|error| format!("{}: {}", path.display(), error)))
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
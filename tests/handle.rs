@@ -0,0 +1,51 @@
+use sandbox::engine::Kind;
+use sandbox::handle::SandboxHandle;
+
+// Only what works without a JS host; the rest returns JS values.
+#[test]
+fn paints_ticks_and_restores() {
+    let mut handle = SandboxHandle::new(20, 10, 7).unwrap();
+    assert_eq!((handle.width(), handle.height()), (20, 10));
+    assert_eq!(handle.frame_len(), 20 * 10 * 4);
+
+    handle.paint(3, 2, "water", 0).unwrap();
+    handle.paint(15, 9, "plant", 1).unwrap();
+    assert_eq!(handle.sandbox().get(3, 2).kind, Kind::Empty);
+    handle.tick();
+    assert_eq!(handle.sandbox().get(3, 2).kind, Kind::Water);
+    assert_eq!(handle.sandbox().get(16, 9).kind, Kind::Plant);
+    let snapshot = handle.snapshot();
+
+    let frame = handle.frame_ptr();
+    for _ in 0..10 {
+        handle.tick();
+    }
+    let x = (0..20).find(|x| handle.sandbox().get(*x, 9).kind == Kind::Water).unwrap();
+    assert_eq!(handle.frame_ptr(), frame);
+    let color = handle.sandbox().get(x, 9).extra.color;
+    let pixels = handle.sandbox().frame().pixel(x, 9);
+    assert_eq!(pixels, [color.r, color.g, color.b, 255]);
+
+    handle.restore(&snapshot).unwrap();
+    assert_eq!(handle.sandbox().get(3, 2).kind, Kind::Water);
+    assert_eq!(handle.sandbox().get(x, 9).kind, Kind::Empty);
+
+    // Handles with the same seed play out the same, though new particles'
+    // colors vary.
+    let mut handles = [SandboxHandle::new(20, 10, 3).unwrap(), SandboxHandle::new(20, 10, 3).unwrap()];
+    for handle in &mut handles {
+        handle.paint(10, 0, "water", 3).unwrap();
+        for _ in 0..20 {
+            handle.tick();
+        }
+    }
+    assert_eq!(handles[0].sandbox().to_ascii(), handles[1].sandbox().to_ascii());
+}
+
+// Errors are JS strings, which need a JS host.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen_test::wasm_bindgen_test]
+fn rejects_empty_worlds() {
+    assert!(SandboxHandle::new(0, 10, 7).is_err());
+    assert!(SandboxHandle::new(20, -1, 7).is_err());
+}
@@ -0,0 +1,97 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::input::{PointerInput, PointerKind, Pointers};
+use sandbox::render::Camera;
+use sandbox::tools::Modifiers;
+
+fn input(id: i32, kind: PointerKind, x: i32, y: i32) -> PointerInput {
+    PointerInput { id, kind, x, y, pressure: 0.5, modifiers: Modifiers::default() }
+}
+
+fn positions(events: &[UserEvent]) -> Vec<(i32, i32, u32)> {
+    events.iter().map(|event| (event.x, event.y, event.size)).collect()
+}
+
+#[test]
+fn paints_with_every_pointer() {
+    let mut camera = Camera::new();
+    let brush = UserEvent::new(0, 0, Kind::Sand, 4);
+    let mut pointers = Pointers::new();
+    pointers.down(input(1, PointerKind::Touch, 5, 6), 0, &camera);
+    pointers.down(input(2, PointerKind::Mouse, 30, 6), 0, &camera);
+    let events = pointers.events(&camera, 50, 50, &brush);
+    assert_eq!(positions(&events), [(5, 6, 4), (30, 6, 4)]);
+
+    pointers.moved(input(1, PointerKind::Touch, 7, 6), &mut camera);
+    let events = pointers.events(&camera, 50, 50, &brush);
+    assert_eq!(events[0].previous, Some((5, 6)));
+    assert_eq!(events[1].previous, Some((30, 6)));
+
+    pointers.up(2);
+    pointers.moved(input(1, PointerKind::Touch, 70, 6), &mut camera);
+    assert!(pointers.events(&camera, 50, 50, &brush).is_empty());
+
+    let mut sandbox = Sandbox::new(50, 50);
+    sandbox.tick_with(vec![UserEvent::new(5, 40, Kind::Water, 0), UserEvent::new(30, 40, Kind::Plant, 0)]);
+    assert_eq!(sandbox.get(5, 40).kind, Kind::Water);
+    assert_eq!(sandbox.get(30, 40).kind, Kind::Plant);
+}
+
+#[test]
+fn scales_the_brush_with_pen_pressure() {
+    let camera = Camera::new();
+    let brush = UserEvent::new(0, 0, Kind::Sand, 10);
+    let mut pointers = Pointers::new();
+    pointers.down(PointerInput { pressure: 1.0, ..input(1, PointerKind::Pen, 1, 1) }, 0, &camera);
+    pointers.down(PointerInput { pressure: 0.25, ..input(2, PointerKind::Pen, 2, 2) }, 0, &camera);
+    pointers.down(PointerInput { pressure: 0.0, ..input(3, PointerKind::Pen, 3, 3) }, 0, &camera);
+    pointers.down(PointerInput { pressure: 1.0, ..input(4, PointerKind::Mouse, 4, 4) }, 0, &camera);
+    let events = pointers.events(&camera, 50, 50, &brush);
+    assert_eq!(positions(&events), [(1, 1, 20), (2, 2, 5), (3, 3, 1), (4, 4, 10)]);
+}
+
+#[test]
+fn pinches_to_zoom_and_pans() {
+    let mut camera = Camera::new();
+    let brush = UserEvent::new(0, 0, Kind::Sand, 1);
+    let mut pointers = Pointers::new();
+    pointers.down(input(1, PointerKind::Touch, 10, 20), 0, &camera);
+    pointers.down(input(2, PointerKind::Touch, 30, 20), 0, &camera);
+    assert!(pointers.events(&camera, 100, 100, &brush).is_empty());
+
+    // Spreading to three times the distance zooms in three times around the
+    // point between the fingers.
+    pointers.moved(input(2, PointerKind::Touch, 70, 20), &mut camera);
+    assert_eq!(camera.zoom, 3);
+    assert_eq!(camera.to_world(40, 20), (20, 20));
+
+    pointers.moved(input(1, PointerKind::Touch, 20, 20), &mut camera);
+    pointers.moved(input(2, PointerKind::Touch, 80, 20), &mut camera);
+    assert_eq!(camera.zoom, 3);
+    assert_eq!(camera.to_world(50, 20), (20, 20));
+
+    // A third finger doesn't paint, nor does one left after the pinch.
+    pointers.down(input(3, PointerKind::Touch, 5, 5), 0, &camera);
+    pointers.up(1);
+    pointers.up(2);
+    assert!(pointers.events(&camera, 100, 100, &brush).is_empty());
+
+    let mut camera = Camera::new();
+    let mut pointers = Pointers::new();
+    pointers.down(input(1, PointerKind::Mouse, 10, 10), 2, &camera);
+    pointers.moved(input(1, PointerKind::Mouse, 15, 7), &mut camera);
+    assert_eq!((camera.x, camera.y), (5, -3));
+    assert!(pointers.events(&camera, 100, 100, &brush).is_empty());
+}
+
+#[test]
+fn tracks_hovering() {
+    let mut camera = Camera::new();
+    let mut pointers = Pointers::new();
+    assert!(!pointers.hovering());
+    pointers.moved(input(1, PointerKind::Mouse, 3, 4), &mut camera);
+    assert!(pointers.hovering());
+    assert_eq!(pointers.hovered(), (3, 4));
+    pointers.left();
+    assert!(!pointers.hovering());
+    assert_eq!(pointers.hovered(), (3, 4));
+}
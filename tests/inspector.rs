@@ -0,0 +1,42 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+
+#[test]
+fn inspects_cells() {
+    let mut sandbox = Sandbox::from_ascii("
+        S.
+        PW
+    ").unwrap();
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Fire, 0)));
+
+    let fire = sandbox.inspect(1, 0).unwrap();
+    assert_eq!((fire.x, fire.y, fire.kind), (1, 0, Kind::Fire));
+    assert_eq!(fire.color, sandbox.get(1, 0).extra.color);
+    assert_eq!(fire.clock, sandbox.clock());
+    assert_eq!(fire.field("activity"), Some(1.0));
+    assert_eq!(fire.field("energy"), Some(fire.energy));
+    assert_eq!(fire.field("velocity"), None);
+
+    let plant = sandbox.inspect(0, 1).unwrap();
+    assert_eq!(plant.kind, Kind::Plant);
+    assert_eq!(plant.energy, 1.0);
+    assert_eq!(plant.field("activity"), Some(0.0));
+    assert!(plant.to_string().starts_with("plant at (0, 1): color #"));
+
+    assert_eq!(sandbox.inspect(2, 0), None);
+    assert_eq!(sandbox.inspect(0, -1), None);
+}
+
+#[test]
+fn starts_particles_from_their_material_color() {
+    let materials: Vec<Kind> = Kind::materials().collect();
+    assert_eq!(materials, [Kind::Sand, Kind::Plant, Kind::Fire, Kind::Water, Kind::Empty]);
+
+    let sandbox = Sandbox::from_ascii("SPFW.").unwrap();
+    for (x, kind) in materials.into_iter().enumerate() {
+        let (actual, expected) = (sandbox.inspect(x as i32, 0).unwrap().color, kind.color());
+        for (actual, expected) in [(actual.r, expected.r), (actual.g, expected.g), (actual.b, expected.b)].iter() {
+            // New sand and plants are lightened or darkened a little at random.
+            assert!((*actual as i32 - *expected as i32).abs() <= 24, "{:?}: {:?}", kind, actual);
+        }
+    }
+}
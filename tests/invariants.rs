@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use proptest::prelude::*;
+use rand::Rng;
+use sandbox::engine::{Behavior, Kind, Particle, Sandbox, UserEvent, WorldView};
+
+const NEIGHBORS: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+fn counts(sandbox: &Sandbox) -> [usize; 6] {
+    let mut counts = [0; 6];
+    for c in sandbox.to_ascii().chars().filter(|c| *c != '\n') {
+        counts[Kind::from_char(c).unwrap() as usize] += 1;
+    }
+    counts
+}
+
+/// Worlds of materials that move but never react with each other.
+fn inert_world() -> impl Strategy<Value = String> {
+    (1..24usize, 1..24usize).prop_flat_map(|(width, height)| {
+        proptest::collection::vec(prop::sample::select(vec!['S', 'W', 'P', '.', '.']), width * height)
+            .prop_map(move |cells| {
+                cells.chunks(width).map(|row| row.iter().collect::<String>()).collect::<Vec<_>>().join("\n")
+            })
+    })
+}
+
+/// Swaps with a random neighbor, whatever is there, counting its updates.
+struct Drift(Rc<Cell<usize>>);
+
+impl<R: Rng> Behavior<R> for Drift {
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        self.0.set(self.0.get() + 1);
+        let (d_x, d_y) = NEIGHBORS[rng.gen_range(0, NEIGHBORS.len())];
+        view.swap(d_x, d_y);
+    }
+}
+
+/// Writes fire only where the view reports out of bounds.
+struct Scatter;
+
+impl<R: Rng> Behavior<R> for Scatter {
+    fn update(&self, view: &mut WorldView, rng: &mut R) {
+        for _ in 0..8 {
+            let d_x = rng.gen_range(-30, 30);
+            let d_y = rng.gen_range(-30, 30);
+            if view.get(d_x, d_y).kind == Kind::OutOfBounds {
+                view.set(d_x, d_y, Particle::new(Kind::Fire));
+            }
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn conserves_inert_materials(world in inert_world(), seed in any::<u64>(), ticks in 1..60u32) {
+        let mut sandbox = Sandbox::from_ascii(&world).unwrap();
+        sandbox.seed(seed);
+        let before = counts(&sandbox);
+        for _ in 0..ticks {
+            sandbox.tick(None);
+            prop_assert_eq!(counts(&sandbox), before);
+        }
+    }
+
+    #[test]
+    fn moves_every_particle_at_most_once_per_tick(world in inert_world(), seed in any::<u64>(), ticks in 1..30u32) {
+        let updates = Rc::new(Cell::new(0));
+        let mut sandbox = Sandbox::from_ascii(&world).unwrap();
+        sandbox.register_behavior(Kind::Plant, Drift(updates.clone()));
+        sandbox.seed(seed);
+        let before = counts(&sandbox);
+        for _ in 0..ticks {
+            updates.set(0);
+            sandbox.tick(None);
+            prop_assert!(updates.get() <= before[Kind::Plant as usize]);
+            prop_assert_eq!(counts(&sandbox), before);
+        }
+    }
+
+    #[test]
+    fn drops_writes_out_of_bounds(world in inert_world(), seed in any::<u64>()) {
+        let mut sandbox = Sandbox::from_ascii(&world).unwrap();
+        sandbox.register_behavior(Kind::Sand, Scatter);
+        sandbox.register_behavior(Kind::Water, Scatter);
+        sandbox.seed(seed);
+        for _ in 0..5 {
+            sandbox.tick(None);
+        }
+        prop_assert_eq!(sandbox.to_ascii(), world);
+    }
+}
+
+#[test]
+fn conserves_sand_under_the_brush_and_scripts() {
+    let mut sandbox = Sandbox::new(30, 30);
+    sandbox.seed(7);
+    sandbox.tick(Some(UserEvent::new(15, 5, Kind::Sand, 4)));
+    sandbox.register_script(Kind::Water, r"
+        let d_x = if rng.gen_bool(0.5) { 1 } else { -1 };
+        if view.kind(0, 1) == KIND_EMPTY {
+            view.swap(0, 1);
+        } else if view.kind(d_x, 0) == KIND_EMPTY {
+            view.swap(d_x, 0);
+        }
+    ").unwrap();
+    sandbox.tick(Some(UserEvent::new(5, 5, Kind::Water, 3)));
+
+    let before = counts(&sandbox);
+    for _ in 0..200 {
+        sandbox.tick(None);
+        assert_eq!(counts(&sandbox), before);
+    }
+}
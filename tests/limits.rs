@@ -0,0 +1,96 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent, MAX_ERRORS};
+use sandbox::events::Hook;
+
+#[test]
+fn stops_runaway_scripts_without_stopping_the_tick() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_script(Kind::Plant, "loop { }").unwrap();
+    sandbox.register_script(Kind::Fire, "fn deep(n) { deep(n + 1) } deep(0);").unwrap();
+    sandbox.register_script(Kind::Water, r#"let s = "x"; loop { s += s; }"#).unwrap();
+    sandbox.tick(Some(UserEvent::new(0, 0, Kind::Plant, 0)));
+    sandbox.tick(Some(UserEvent::new(2, 0, Kind::Fire, 0)));
+    sandbox.tick(Some(UserEvent::new(4, 0, Kind::Water, 0)));
+    sandbox.tick(Some(UserEvent::new(2, 2, Kind::Sand, 0)));
+
+    let errors = sandbox.errors();
+    assert_eq!(errors.len(), 3);
+    assert!(errors.iter().any(|error| error.starts_with("plant script:")));
+    assert!(errors.iter().any(|error| error.starts_with("fire script:")));
+    assert!(errors.iter().any(|error| error.starts_with("water script:")));
+    assert_eq!(sandbox.get(2, 2).kind, Kind::Sand);
+
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(2, 3).kind, Kind::Sand);
+}
+
+#[test]
+fn caps_errors_per_tick() {
+    let mut sandbox = Sandbox::new(10, 10);
+    sandbox.register_script(Kind::Plant, "view.nothing();").unwrap();
+    sandbox.register_hook(Kind::Plant, Hook::Place, "view.nothing();").unwrap();
+    sandbox.tick(Some(UserEvent::new(5, 5, Kind::Plant, 4)));
+    assert_eq!(sandbox.errors().len(), MAX_ERRORS);
+    assert!(sandbox.errors()[0].starts_with("plant Place hook:"));
+    sandbox.tick(None);
+    assert_eq!(sandbox.errors().len(), MAX_ERRORS);
+    assert!(sandbox.errors()[0].starts_with("plant script:"));
+}
+
+#[test]
+fn keeps_writes_made_before_a_tool_fails() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_tool("broken", "view.set(0, 0, particle(pointer.kind)); view.nothing();").unwrap();
+    sandbox.tick(Some(UserEvent { tool: Some("broken".to_string()), ..UserEvent::new(1, 1, Kind::Plant, 0) }));
+    assert_eq!(sandbox.errors().len(), 1);
+    assert!(sandbox.errors()[0].starts_with("tool `broken`:"));
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Plant);
+
+    sandbox.tick(None);
+    assert!(sandbox.errors().is_empty());
+}
+
+#[test]
+fn rejects_scripts_that_cannot_be_parsed_safely() {
+    let mut sandbox = Sandbox::new(5, 5);
+    let nested = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+    assert!(sandbox.register_script(Kind::Plant, &nested).is_err());
+
+    for script in &["view.1", "view.(0)", "let a = current.kind.[0];", "let x = 1.5.2;"] {
+        let error = sandbox.register_script(Kind::Plant, script).unwrap_err();
+        assert_eq!(error.1.line(), Some(1), "{}", script);
+    }
+    for script in &["/* view.1 */ let a = \"view.(0)\";", "let f = 1.5 * 2.0; let k = current . kind;"] {
+        assert!(sandbox.register_script(Kind::Plant, script).is_ok(), "{}", script);
+    }
+    assert!(sandbox.register_tool("broken", "pointer.(x)").is_err());
+    assert!(sandbox.generate("world.0", 0).is_err());
+}
+
+#[test]
+fn rejects_gen_bool_probabilities_outside_zero_to_one() {
+    for p in &["1.5", "-0.1", "0.0 / 0.0"] {
+        let mut sandbox = Sandbox::new(5, 5);
+        sandbox.register_script(Kind::Plant, &format!("rng.gen_bool({});", p)).unwrap();
+        sandbox.tick(Some(UserEvent::new(2, 2, Kind::Plant, 0)));
+        sandbox.tick(None);
+        assert_eq!(sandbox.errors().len(), 1, "{}", p);
+        assert!(sandbox.errors()[0].contains("gen_bool"), "{}", sandbox.errors()[0]);
+    }
+}
+
+#[test]
+fn treats_overflowing_offsets_as_out_of_bounds() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_script(Kind::Plant, r"
+        let max = 2147483647;
+        if view.kind(max, 0) != KIND_OUT_OF_BOUNDS || view.kind(0, 0 - max - 1) != KIND_OUT_OF_BOUNDS {
+            throw;
+        }
+        view.set(max, max, particle(KIND_WATER));
+        view.swap(max, 0);
+    ").unwrap();
+    sandbox.tick(Some(UserEvent::new(2, 2, Kind::Plant, 0)));
+    sandbox.tick(None);
+    assert!(sandbox.errors().is_empty(), "{:?}", sandbox.errors());
+    assert_eq!(sandbox.get(2, 2).kind, Kind::Plant);
+}
@@ -0,0 +1,51 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::patterns::{Cell, Pattern};
+
+static FALLING: &str = r#"
+    rule(["***", "*P*", "*.*"], ["***", "*.*", "*P*"]);
+    rule("*** *P* *P.", "*** *.* *PP", #{ mirror_x: true });
+"#;
+
+#[test]
+fn applies_patterns() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_patterns(Kind::Plant, FALLING).unwrap();
+    sandbox.tick(Some(UserEvent::new(2, 0, Kind::Plant, 0)));
+    for _ in 0..10 {
+        sandbox.tick(None);
+    }
+    assert_eq!(sandbox.get(2, 0).kind, Kind::Empty);
+    assert_eq!(sandbox.get(2, 4).kind, Kind::Plant);
+}
+
+#[test]
+fn mirrors_patterns() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_patterns(Kind::Plant, r#"
+        rule("*** *P. ***", "*** *.P ***", #{ mirror_x: true });
+    "#).unwrap();
+    sandbox.tick(Some(UserEvent::new(2, 2, Kind::Plant, 0)));
+    sandbox.tick(None);
+    let moved = sandbox.get(1, 2).kind == Kind::Plant || sandbox.get(3, 2).kind == Kind::Plant;
+    assert!(moved);
+    assert_eq!(sandbox.get(2, 2).kind, Kind::Empty);
+}
+
+#[test]
+fn parses_patterns() {
+    let pattern = Pattern::new(&["***", "*S*", "*.*"], &["***", "*.*", "*S*"]).unwrap();
+    assert_eq!(pattern.matches[1][1], Cell::Is(Kind::Sand));
+    assert_eq!(pattern.matches[2][1], Cell::Is(Kind::Empty));
+    assert_eq!(pattern.replaces[0][0], Cell::Any);
+
+    assert!(Pattern::new(&["**", "*S*", "*.*"], &["***", "***", "***"]).is_err());
+    assert!(Pattern::new(&["***", "*?*", "***"], &["***", "***", "***"]).is_err());
+
+    let mut sandbox = Sandbox::new(5, 5);
+    assert!(sandbox.register_patterns(Kind::Plant, r#"rule("*** *P*", "*** *.*");"#).is_err());
+    assert!(sandbox.register_patterns(Kind::Plant, r#"rule("*** *P* ***", "*** *.* ***", #{ chance: 2 });"#).is_err());
+    for probability in &["1.5", "-0.5", "0.0 / 0.0"] {
+        let script = format!(r#"rule("*** *P* ***", "*** *.* ***", #{{ probability: {} }});"#, probability);
+        assert!(sandbox.register_patterns(Kind::Plant, &script).is_err(), "{}", probability);
+    }
+}
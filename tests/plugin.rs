@@ -0,0 +1,128 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::events::Hook;
+use sandbox::plugin::{validate, PluginError};
+
+static FALLING: &str = r#"
+    (module
+        (import "env" "get" (func $get (param i32 i32) (result i32)))
+        (import "env" "swap" (func $swap (param i32 i32 i32 i32)))
+        (func (export "update") (param $x i32) (param $y i32)
+            (if (i32.eq (call $get (local.get $x) (i32.add (local.get $y) (i32.const 1))) (i32.const 4))
+                (then (call $swap
+                    (local.get $x) (local.get $y)
+                    (local.get $x) (i32.add (local.get $y) (i32.const 1)))))))
+"#;
+
+static SPREADING: &str = r#"
+    (module
+        (import "env" "set" (func $set (param i32 i32 i32)))
+        (func (export "update") (param $x i32) (param $y i32)
+            (call $set (i32.add (local.get $x) (i32.const 1)) (local.get $y) (i32.const 1))
+            (call $set (i32.const -1) (i32.const -1) (i32.const 1))
+            (call $set (local.get $x) (i32.add (local.get $y) (i32.const 1)) (i32.const 99))))
+"#;
+
+fn plant_at(sandbox: &mut Sandbox, x: i32, y: i32) {
+    sandbox.tick(Some(UserEvent::new(x, y, Kind::Plant, 0)));
+}
+
+#[test]
+fn runs_plugins_natively() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_plugin(Kind::Plant, &wat::parse_str(FALLING).unwrap()).unwrap();
+    plant_at(&mut sandbox, 2, 0);
+    for _ in 0..10 {
+        sandbox.tick(None);
+    }
+    assert_eq!(sandbox.get(2, 0).kind, Kind::Empty);
+    assert_eq!(sandbox.get(2, 4).kind, Kind::Plant);
+}
+
+#[test]
+fn swaps_without_placing_or_destroying() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_plugin(Kind::Plant, &wat::parse_str(FALLING).unwrap()).unwrap();
+    sandbox.register_hook(Kind::Plant, Hook::Place, r#"throw "placed";"#).unwrap();
+    sandbox.register_hook(Kind::Plant, Hook::Destroy, r#"throw "destroyed";"#).unwrap();
+    plant_at(&mut sandbox, 2, 0);
+    assert_eq!(sandbox.errors().len(), 1);
+    for _ in 0..6 {
+        sandbox.tick(None);
+        assert!(sandbox.errors().is_empty(), "{:?}", sandbox.errors());
+    }
+    assert_eq!(sandbox.get(2, 4).kind, Kind::Plant);
+}
+
+#[test]
+fn stops_plugins_that_run_forever() {
+    let runaway = wat::parse_str(r#"
+        (module
+            (import "env" "set" (func $set (param i32 i32 i32)))
+            (func (export "update") (param $x i32) (param $y i32)
+                (call $set (i32.add (local.get $x) (i32.const 1)) (local.get $y) (i32.const 0))
+                (loop (br 0))))
+    "#).unwrap();
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_plugin(Kind::Plant, &runaway).unwrap();
+    plant_at(&mut sandbox, 0, 4);
+    plant_at(&mut sandbox, 3, 4);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 4).kind, Kind::Sand);
+    assert_eq!(sandbox.get(4, 4).kind, Kind::Sand);
+}
+
+#[test]
+fn runs_start_functions() {
+    let starting = wat::parse_str(r#"
+        (module
+            (import "env" "set" (func $set (param i32 i32 i32)))
+            (global $kind (mut i32) (i32.const 0))
+            (func $init
+                (local $i i32)
+                (loop
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br_if 0 (i32.lt_u (local.get $i) (i32.const 2))))
+                (global.set $kind (local.get $i)))
+            (start $init)
+            (func (export "update") (param $x i32) (param $y i32)
+                (call $set (i32.add (local.get $x) (i32.const 1)) (local.get $y) (global.get $kind))))
+    "#).unwrap();
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_plugin(Kind::Plant, &starting).unwrap();
+    plant_at(&mut sandbox, 0, 4);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 4).kind, Kind::Fire);
+}
+
+#[test]
+fn ignores_invalid_writes() {
+    let mut sandbox = Sandbox::new(5, 5);
+    sandbox.register_plugin(Kind::Plant, &wat::parse_str(SPREADING).unwrap()).unwrap();
+    plant_at(&mut sandbox, 0, 0);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 0).kind, Kind::Plant);
+    assert_eq!(sandbox.get(0, 1).kind, Kind::Empty);
+}
+
+#[test]
+fn rejects_modules_breaking_the_abi() {
+    let wrong_signature = wat::parse_str(r#"
+        (module
+            (import "env" "get" (func (param i32) (result i32)))
+            (func (export "update") (param i32 i32)))
+    "#).unwrap();
+    let unknown_import = wat::parse_str(r#"
+        (module
+            (import "env" "print" (func (param i32)))
+            (func (export "update") (param i32 i32)))
+    "#).unwrap();
+    let missing_update = wat::parse_str("(module (func (export \"tick\")))").unwrap();
+
+    for bytes in &[wrong_signature, unknown_import, missing_update] {
+        match validate(bytes) {
+            Err(PluginError::Invalid(_)) => {}
+            result => panic!("expected the plugin to be rejected, got {:?}", result),
+        }
+    }
+    assert!(validate(b"not wasm").is_err());
+}
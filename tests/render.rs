@@ -0,0 +1,195 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::render::{BrushPreview, Camera, FrameBuffer, Overlay, Renderer};
+
+#[test]
+fn renders_particle_colors_into_a_reused_buffer() {
+    let mut sandbox = Sandbox::from_ascii("
+        S..
+        .W.
+        ..P
+    ").unwrap();
+    let mut frame = FrameBuffer::new(3, 3);
+    frame.render(&sandbox);
+    assert_eq!(frame.data().len(), 3 * 3 * 4);
+
+    for (index, particle) in sandbox.particles().iter().enumerate() {
+        let (x, y) = (index as i32 % 3, index as i32 / 3);
+        let color = particle.extra.color;
+        assert_eq!(frame.pixel(x, y), [color.r, color.g, color.b, 255]);
+    }
+
+    let data = frame.data().as_ptr();
+    sandbox.tick(Some(UserEvent::new(2, 0, Kind::Fire, 0)));
+    frame.render(&sandbox);
+    assert_eq!(frame.data().as_ptr(), data);
+    let fire = sandbox.get(2, 0).extra.color;
+    assert_eq!(frame.pixel(2, 0), [fire.r, fire.g, fire.b, 255]);
+}
+
+#[test]
+fn resizes_to_the_world() {
+    let sandbox = Sandbox::new(5, 2);
+    let mut frame = FrameBuffer::new(1, 1);
+    frame.render(&sandbox);
+    assert_eq!((frame.width(), frame.height()), (5, 2));
+    assert_eq!(frame.data().len(), 5 * 2 * 4);
+}
+
+fn full_frame(sandbox: &Sandbox) -> Vec<u8> {
+    let mut frame = FrameBuffer::new(1, 1);
+    frame.render(sandbox);
+    frame.data().to_vec()
+}
+
+#[test]
+fn keeps_the_sandbox_frame_up_to_date() {
+    let mut sandbox = Sandbox::new(20, 20);
+    sandbox.seed(3);
+    assert_eq!(sandbox.frame().data(), &full_frame(&sandbox)[..]);
+
+    let data = sandbox.frame().data().as_ptr();
+    sandbox.tick(Some(UserEvent::new(10, 2, Kind::Sand, 3)));
+    sandbox.tick(Some(UserEvent::new(4, 2, Kind::Water, 2)));
+    let snapshot = sandbox.snapshot();
+    for _ in 0..30 {
+        sandbox.tick(None);
+        assert_eq!(sandbox.frame().data(), &full_frame(&sandbox)[..]);
+    }
+    assert_eq!(sandbox.frame().data().as_ptr(), data);
+
+    sandbox.restore(&snapshot).unwrap();
+    assert_eq!(sandbox.frame().data(), &full_frame(&sandbox)[..]);
+}
+
+#[test]
+fn maps_the_screen_through_the_camera() {
+    let mut camera = Camera::new();
+    assert_eq!(camera.to_world(7, 3), (7, 3));
+
+    camera.zoom_at(40, 20, 3);
+    assert_eq!(camera.zoom, 4);
+    assert_eq!(camera.to_world(40, 20), (40, 20));
+    assert_eq!(camera.to_world(43, 23), (40, 20));
+    assert_eq!(camera.to_world(44, 20), (41, 20));
+
+    camera.pan(-8, 4);
+    assert_eq!(camera.to_world(40, 20), (42, 19));
+    let (x, y) = camera.to_screen(42, 19);
+    assert_eq!(camera.to_world(x, y), (42, 19));
+    assert_eq!(camera.to_world(x - 1, y - 1), (41, 18));
+
+    camera.zoom_at(0, 0, -100);
+    assert_eq!(camera.zoom, 1);
+    camera.zoom_at(0, 0, 100);
+    assert_eq!(camera.zoom, Camera::MAX_ZOOM);
+}
+
+#[test]
+fn draws_overlays_and_their_legends() {
+    let mut sandbox = Sandbox::from_ascii("
+        SW
+        .P
+    ").unwrap();
+    sandbox.set_overlay(Overlay::Material);
+    let legend = Overlay::Material.legend();
+    let color = |name: &str| legend.iter().find(|(label, _)| label == name).unwrap().1;
+    let [r, g, b] = color("plant");
+    assert_eq!(sandbox.frame().pixel(1, 1), [r, g, b, 255]);
+    assert_ne!(color("sand"), color("water"));
+
+    let activity = Overlay::from_name("activity").unwrap();
+    let legend = activity.legend();
+    assert_eq!(legend.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>(), ["0", "1"]);
+    let mut sandbox = Sandbox::from_ascii("
+        S.
+        SS
+    ").unwrap();
+    sandbox.set_overlay(activity);
+    sandbox.tick(Some(UserEvent::new(1, 0, Kind::Sand, 0)));
+    let level = |sandbox: &Sandbox, x, y| {
+        let [r, g, b, _] = sandbox.frame().pixel(x, y);
+        legend.iter().position(|(_, color)| *color == [r, g, b]).unwrap()
+    };
+    assert_eq!(level(&sandbox, 1, 0), 1);
+    assert_eq!(level(&sandbox, 0, 0), 0);
+    sandbox.tick(None);
+    assert_eq!(level(&sandbox, 1, 0), 0);
+
+    let mut frame = FrameBuffer::new(1, 1);
+    frame.set_overlay(activity);
+    frame.render(&sandbox);
+    assert_eq!(frame.data(), sandbox.frame().data());
+}
+
+#[test]
+fn lights_the_frame() {
+    let ascii = "
+        ........
+        ........
+        .F......
+        ......SS
+        WWWWWWSS
+        SSSSSSSS
+    ";
+    let mut sandbox = Sandbox::from_ascii(ascii).unwrap();
+    let flat = sandbox.frame().data().to_vec();
+    sandbox.set_lighting(true);
+    let brightness = |data: &[u8], x: i32, y: i32| {
+        let i = ((x + y * 8) * 4) as usize;
+        data[i] as u32 + data[i + 1] as u32 + data[i + 2] as u32
+    };
+    let lit = sandbox.frame().data();
+
+    // Fire glows onto the empty cells near it, less with distance.
+    assert!(brightness(lit, 2, 2) > brightness(lit, 5, 2));
+    assert!(brightness(lit, 5, 2) > 0);
+    assert_eq!(brightness(lit, 1, 2), brightness(&flat, 1, 2));
+    // Sand is darker further below its surface.
+    assert!(brightness(lit, 7, 5) < brightness(lit, 7, 3));
+    // Water takes on some of the sand under it.
+    assert!(lit[(4 * 8 + 3) * 4] > flat[(4 * 8 + 3) * 4]);
+
+    // Lit frames stay consistent as the world changes.
+    for _ in 0..5 {
+        sandbox.tick(None);
+    }
+    let mut frame = FrameBuffer::new(1, 1);
+    frame.set_lighting(true);
+    frame.render(&sandbox);
+    assert_eq!(frame.data(), sandbox.frame().data());
+
+    sandbox.set_lighting(false);
+    assert_eq!(sandbox.frame().data(), &full_frame(&sandbox)[..]);
+}
+
+#[test]
+fn outlines_the_brush() {
+    let square = BrushPreview::new(10, 10, Kind::Sand, 2, None);
+    assert_eq!(square.color, Kind::Sand.color());
+    assert_eq!(square.outline(), [
+        (8, 8, 5),
+        (8, 9, 1), (12, 9, 1),
+        (8, 10, 1), (12, 10, 1),
+        (8, 11, 1), (12, 11, 1),
+        (8, 12, 5),
+    ]);
+    assert_eq!(BrushPreview::new(0, 0, Kind::Sand, 0, None).outline(), [(0, 0, 1)]);
+    assert_eq!(BrushPreview::new(0, 0, Kind::Sand, 4, Some("smudge")).radius, 3);
+
+    // Every covered cell is on the outline or has covered neighbors all
+    // around, and every outlined cell is covered.
+    let circle = BrushPreview::new(20, 20, Kind::Empty, 7, Some("spray"));
+    assert_ne!(circle.color, Kind::Empty.color());
+    let outlined: Vec<(i32, i32)> = circle.outline().into_iter()
+        .flat_map(|(x, y, length)| (x..x + length).map(move |x| (x, y)))
+        .collect();
+    for y in 10..=30 {
+        for x in 10..=30 {
+            let inside = [(0, -1), (0, 1), (-1, 0), (1, 0)].iter()
+                .all(|(d_x, d_y)| circle.covers(x + d_x, y + d_y));
+            let expected = circle.covers(x, y) && !inside;
+            assert_eq!(outlined.contains(&(x, y)), expected, "({}, {})", x, y);
+        }
+    }
+    assert!(circle.covers(27, 20) && !circle.covers(28, 20) && !circle.covers(26, 26));
+}
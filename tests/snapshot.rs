@@ -0,0 +1,66 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::snapshot::SnapshotError;
+
+// Plants wither on their third update, counted across ticks.
+static WITHER: &str = r#"
+    let updates = state.get("updates", 0) + 1;
+    state.set("updates", updates);
+    global.set("last_clock", clock);
+    if updates == 3 {
+        view.set(0, 0, EMPTY);
+    }
+"#;
+
+fn sandbox() -> Sandbox {
+    let mut sandbox = Sandbox::new(4, 3);
+    sandbox.register_script(Kind::Plant, WITHER).unwrap();
+    sandbox
+}
+
+#[test]
+fn keeps_script_state_across_ticks() {
+    let mut sandbox = sandbox();
+    sandbox.tick(Some(UserEvent::new(1, 1, Kind::Plant, 0)));
+    sandbox.tick(None);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Plant);
+    sandbox.tick(None);
+    assert_eq!(sandbox.get(1, 1).kind, Kind::Empty);
+}
+
+#[test]
+fn restores_world_and_script_state() {
+    let mut original = sandbox();
+    original.tick(Some(UserEvent::new(1, 1, Kind::Plant, 0)));
+    original.tick(Some(UserEvent::new(3, 0, Kind::Water, 0)));
+    original.tick(None);
+    let bytes = original.snapshot();
+
+    let mut restored = Sandbox::new(10, 10);
+    restored.register_script(Kind::Plant, WITHER).unwrap();
+    restored.restore(&bytes).unwrap();
+    assert_eq!(restored.snapshot(), bytes);
+    assert_eq!(restored.get(1, 1), original.get(1, 1));
+    assert_eq!(restored.get(5, 5).kind, Kind::OutOfBounds);
+
+    restored.tick(None);
+    assert_eq!(restored.get(1, 1).kind, Kind::Empty);
+}
+
+#[test]
+fn rejects_bad_snapshots() {
+    let mut sandbox = sandbox();
+    sandbox.tick(Some(UserEvent::new(1, 1, Kind::Plant, 0)));
+    let bytes = sandbox.snapshot();
+
+    assert_eq!(sandbox.restore(b"nope"), Err(SnapshotError::BadMagic));
+    assert_eq!(sandbox.restore(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated));
+    let mut version = bytes.clone();
+    version[4] = 99;
+    assert_eq!(sandbox.restore(&version), Err(SnapshotError::UnsupportedVersion(99)));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(sandbox.restore(&trailing).is_err());
+
+    assert_eq!(sandbox.snapshot(), bytes);
+}
@@ -0,0 +1,17 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+
+#[test]
+fn counts_updated_cells_per_material() {
+    let mut sandbox = Sandbox::new(20, 20);
+    sandbox.tick(Some(UserEvent::new(5, 2, Kind::Sand, 1)));
+    sandbox.tick(Some(UserEvent::new(15, 2, Kind::Water, 0)));
+    let stats = sandbox.stats();
+    assert_eq!(stats.material(Kind::Sand).cells, 9);
+    assert_eq!(stats.material(Kind::Water).cells, 0);
+    assert!(stats.material(Kind::Sand).time > 0.0);
+    assert!(stats.time >= stats.material(Kind::Sand).time);
+
+    sandbox.tick(None);
+    assert_eq!(sandbox.stats().material(Kind::Water).cells, 1);
+    assert_eq!(sandbox.stats().material(Kind::Empty).cells, 0);
+}
@@ -0,0 +1,81 @@
+use sandbox::engine::{Kind, Sandbox, UserEvent};
+use sandbox::tools::{Modifiers, TOOLS};
+
+fn with_tool(tool: &str, x: i32, y: i32, kind: Kind, size: u32) -> UserEvent {
+    UserEvent { tool: Some(tool.to_string()), ..UserEvent::new(x, y, kind, size) }
+}
+
+#[test]
+fn runs_custom_tools_with_the_pointer() {
+    let mut sandbox = Sandbox::new(5, 3);
+    sandbox.register_tool("trail", r"
+        view.set(pointer.previous_x - pointer.x, pointer.previous_y - pointer.y, particle(pointer.kind));
+        if pointer.shift {
+            view.set(0, 0, particle(KIND_WATER));
+        }
+    ").unwrap();
+
+    let event = UserEvent {
+        previous: Some((0, 1)),
+        modifiers: Modifiers { shift: true, ..Modifiers::default() },
+        ..with_tool("trail", 3, 1, Kind::Plant, 0)
+    };
+    sandbox.tick(Some(event));
+    assert_eq!(sandbox.to_ascii(), ".....\nP..W.\n.....");
+
+    assert!(sandbox.register_tool("broken", "view.set(").is_err());
+    sandbox.tick(Some(with_tool("missing", 0, 0, Kind::Plant, 0)));
+    assert_eq!(sandbox.get(0, 0).kind, Kind::Empty);
+}
+
+#[test]
+fn vacuums_and_replaces_materials() {
+    let mut sandbox = Sandbox::new(5, 3);
+    sandbox.generate("world.fill(0, 0, 4, 2, KIND_PLANT); world.set(2, 1, KIND_FIRE);", 0).unwrap();
+
+    let shifted = Modifiers { shift: true, ..Modifiers::default() };
+    sandbox.tick(Some(UserEvent { modifiers: shifted, ..with_tool("vacuum", 2, 1, Kind::Fire, 1) }));
+    assert_eq!(sandbox.to_ascii(), "PPPPP\nPP.PP\nPPPPP");
+
+    sandbox.tick(Some(with_tool("vacuum", 0, 0, Kind::Fire, 1)));
+    assert_eq!(sandbox.to_ascii(), "..PPP\n.P.PP\nPPPPP");
+
+    sandbox.tick(Some(with_tool("replace", 4, 2, Kind::Water, 0)));
+    assert_eq!(sandbox.get(4, 2).kind, Kind::Water);
+}
+
+#[test]
+fn smudges_along_the_movement() {
+    let mut sandbox = Sandbox::new(7, 3);
+    sandbox.generate("world.set(1, 1, KIND_PLANT);", 0).unwrap();
+
+    let event = UserEvent { previous: Some((1, 1)), ..with_tool("smudge", 3, 1, Kind::Sand, 0) };
+    sandbox.tick(Some(event));
+    assert_eq!(sandbox.to_ascii(), ".......\n...P...\n.......");
+}
+
+#[test]
+fn sprays_only_into_empty_cells() {
+    let mut sandbox = Sandbox::new(9, 9);
+    sandbox.generate("world.set(4, 4, KIND_PLANT);", 0).unwrap();
+    for _ in 0..50 {
+        sandbox.tick(Some(with_tool("spray", 4, 4, Kind::Water, 3)));
+    }
+
+    let sprayed = sandbox.to_ascii();
+    assert_eq!(sandbox.get(4, 4).kind, Kind::Plant);
+    assert!(sprayed.contains('W'));
+    assert_eq!(sandbox.get(0, 0).kind, Kind::Empty);
+}
+
+// The UI lets brushes grow as wide as the world.
+#[test]
+fn runs_every_tool_at_the_largest_size() {
+    for (tool, _) in TOOLS.iter() {
+        let mut sandbox = Sandbox::new(100, 100);
+        sandbox.generate("world.fill(0, 50, 99, 99, KIND_PLANT);", 0).unwrap();
+        let event = UserEvent { previous: Some((40, 45)), ..with_tool(tool, 50, 50, Kind::Water, 100) };
+        sandbox.tick(Some(event));
+        assert!(sandbox.errors().is_empty(), "{}: {:?}", tool, sandbox.errors());
+    }
+}